@@ -0,0 +1,249 @@
+use glutin::event::VirtualKeyCode;
+use crate::components::Lifetime;
+use crate::consts::SPINNER_CENTER;
+use crate::difficulty::PlayDifficulty;
+use crate::game_thread::EventLoopMsg;
+use crate::mods::{Mod, Mods};
+use crate::resources::{key_vk_for_key_binding, GameInputKeyBinding};
+use crate::utils::lerp;
+
+// how long a circle tap holds the key down
+const TAP_DURATION: f64 = 0.050;
+// radians per sec, well above what any spinner asks for
+const SPIN_SPEED: f64 = 50.0;
+// distance from the spinner center the cursor circles at, in osu! pixels
+const SPIN_RADIUS: f32 = 50.0;
+
+// what the bot needs to know about an object that's still around
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum BotObject {
+    Circle,
+    // not judged yet, has to be clicked
+    SliderHead,
+    // head is done, the ball has to be followed
+    SliderBall,
+    Spinner { end_at: f64 },
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct BotTarget {
+    pub object: BotObject,
+    pub lifetime: Lifetime,
+    // game area position, the ball for a running slider
+    pub pos: (f32, f32),
+}
+
+// plays the map perfectly, the input it makes goes through the same path as real input.
+// relax only takes over the keys and autopilot only the cursor
+pub struct Autoplay {
+    moves_cursor: bool,
+    presses_keys: bool,
+    // objects are clicked once the cursor is this close, in osu! pixels
+    circle_radius: f32,
+    meh_window: f64,
+    // in game area coordinates, the window ones change with the window size
+    cursor: (f32, f32),
+    spin_angle: f64,
+    // key held down and when to let go of it, None when held for a slider or spinner
+    held: Option<(VirtualKeyCode, Option<f64>)>,
+    // keys alternate between taps
+    use_hit2: bool,
+    last_frame_at: Option<f64>,
+}
+
+impl Autoplay {
+    // None when no mod asks for synthetic input
+    pub fn new(mods: Mods, difficulty: &PlayDifficulty) -> Option<Self> {
+        let auto = mods.contains(Mod::Auto);
+        let moves_cursor = auto || mods.contains(Mod::Autopilot);
+        let presses_keys = auto || mods.contains(Mod::Relax);
+        if !moves_cursor && !presses_keys {
+            return None;
+        }
+        Some(Self {
+            moves_cursor,
+            presses_keys,
+            circle_radius: difficulty.circle_radius,
+            meh_window: difficulty.hit_windows.meh,
+            cursor: SPINNER_CENTER,
+            spin_angle: 0.0,
+            held: None,
+            use_hit2: false,
+            last_frame_at: None,
+        })
+    }
+
+    pub fn moves_cursor(&self) -> bool {
+        self.moves_cursor
+    }
+
+    pub fn presses_keys(&self) -> bool {
+        self.presses_keys
+    }
+
+    pub fn cursor(&self) -> (f32, f32) {
+        self.cursor
+    }
+
+    // `targets` have to be sorted by hit time, `player_cursor` is where the player
+    // put the cursor in game area coordinates. returns the key events for this frame.
+    // only `hit_at` of the lifetimes is used, `remaining` lags a frame behind
+    // until LifetimeSystem has run
+    pub fn frame(&mut self, now: f64, targets: &[BotTarget], player_cursor: Option<(f32, f32)>) -> Vec<EventLoopMsg> {
+        // the clock going back means the map got restarted
+        if matches!(self.last_frame_at, Some(last_frame_at) if now < last_frame_at) {
+            self.cursor = SPINNER_CENTER;
+            self.spin_angle = 0.0;
+            self.held = None;
+            self.use_hit2 = false;
+        }
+        let delta = now - self.last_frame_at.unwrap_or(now);
+        self.last_frame_at = Some(now);
+        if !self.moves_cursor {
+            if let Some(player_cursor) = player_cursor {
+                self.cursor = player_cursor;
+            }
+        }
+
+        let mut events = Vec::new();
+        if let Some((key, Some(release_at))) = self.held {
+            if now >= release_at {
+                events.push(EventLoopMsg::KeyReleased(key));
+                self.held = None;
+            }
+        }
+
+        // sliders and spinners that already started are held down until they end
+        let running = targets.iter().find(|target| target.lifetime.hit_at <= now && match target.object {
+            BotObject::SliderBall => true,
+            BotObject::Spinner { end_at } => now < end_at,
+            _ => false,
+        });
+        if let Some(running) = running {
+            if self.moves_cursor {
+                self.cursor = if let BotObject::Spinner { .. } = running.object {
+                    self.spin_angle += delta * SPIN_SPEED;
+                    self.spin_position()
+                } else {
+                    running.pos
+                };
+            }
+            if !self.presses_keys {
+                return events;
+            }
+            match self.held {
+                Some((key, Some(_))) => self.held = Some((key, None)),
+                Some((_, None)) => {}
+                None => self.press(None, &mut events),
+            }
+            return events;
+        }
+
+        if let Some((key, None)) = self.held {
+            events.push(EventLoopMsg::KeyReleased(key));
+            self.held = None;
+        }
+
+        let next = match targets.iter().find(|target| target.object != BotObject::SliderBall) {
+            Some(next) => next,
+            None => return events,
+        };
+        let goal = if let BotObject::Spinner { .. } = next.object {
+            self.spin_position()
+        } else {
+            next.pos
+        };
+        // straight towards the next object, arriving right on time
+        if self.moves_cursor {
+            let until_hit = next.lifetime.hit_at - now;
+            self.cursor = if until_hit <= 0.0 {
+                goal
+            } else {
+                let t = (delta / (until_hit + delta)) as f32;
+                (lerp(self.cursor.0, goal.0, t), lerp(self.cursor.1, goal.1, t))
+            };
+        }
+
+        // clicked on time, or as soon as the cursor gets there while it can still be hit
+        let remaining = next.lifetime.hit_at - now;
+        if self.presses_keys && remaining <= 0.0 && remaining > -self.meh_window && self.is_hovering(next.pos) {
+            match next.object {
+                BotObject::Circle => self.press(Some(now + TAP_DURATION), &mut events),
+                BotObject::SliderHead => self.press(None, &mut events),
+                _ => {}
+            }
+        }
+        events
+    }
+
+    fn press(&mut self, release_at: Option<f64>, events: &mut Vec<EventLoopMsg>) {
+        if let Some((key, _)) = self.held {
+            events.push(EventLoopMsg::KeyReleased(key));
+        }
+        let binding = if self.use_hit2 { GameInputKeyBinding::Hit2 } else { GameInputKeyBinding::Hit1 };
+        let key = key_vk_for_key_binding(binding);
+        events.push(EventLoopMsg::KeyPressed(key));
+        self.held = Some((key, release_at));
+        self.use_hit2 = !self.use_hit2;
+    }
+
+    fn is_hovering(&self, pos: (f32, f32)) -> bool {
+        let (dx, dy) = (self.cursor.0 - pos.0, self.cursor.1 - pos.1);
+        dx * dx + dy * dy <= self.circle_radius * self.circle_radius
+    }
+
+    fn spin_position(&self) -> (f32, f32) {
+        (
+            SPINNER_CENTER.0 + SPIN_RADIUS * self.spin_angle.cos() as f32,
+            SPINNER_CENTER.1 + SPIN_RADIUS * self.spin_angle.sin() as f32,
+        )
+    }
+}
+
+#[test]
+fn autoplay_test() {
+    use std::str::FromStr;
+
+    let circle = |hit_at: f64, now: f64, pos: (f32, f32)| BotTarget {
+        object: BotObject::Circle,
+        lifetime: Lifetime { remaining: hit_at - now, hit_at },
+        pos,
+    };
+
+    let difficulty = PlayDifficulty::from_settings(4.0, 9.0, 8.0, 5.0, Mods::default());
+    assert!(Autoplay::new(Mods::default(), &difficulty).is_none());
+
+    let mut autoplay = Autoplay::new(Mods::from_str("AT").unwrap(), &difficulty).unwrap();
+    assert!(autoplay.frame(0.0, &[circle(1.0, 0.0, (100.0, 100.0))], None).is_empty());
+
+    // halfway there halfway through
+    autoplay.frame(0.5, &[circle(1.0, 0.0, (100.0, 100.0))], None);
+    let cursor = autoplay.cursor();
+    assert!((cursor.0 - (SPINNER_CENTER.0 + 100.0) / 2.0).abs() < 1e-3);
+
+    let events = autoplay.frame(1.0, &[circle(1.0, 1.0, (100.0, 100.0))], None);
+    assert_eq!(autoplay.cursor(), (100.0, 100.0));
+    assert!(matches!(events[..], [EventLoopMsg::KeyPressed(VirtualKeyCode::G)]));
+
+    // the tap is let go and the next one uses the other key
+    let events = autoplay.frame(1.1, &[circle(1.1, 1.1, (200.0, 100.0))], None);
+    assert!(matches!(events[..], [EventLoopMsg::KeyReleased(VirtualKeyCode::G), EventLoopMsg::KeyPressed(VirtualKeyCode::H)]));
+}
+
+#[test]
+fn relax_test() {
+    use std::str::FromStr;
+
+    let target = BotTarget {
+        object: BotObject::Circle,
+        lifetime: Lifetime { remaining: -0.01, hit_at: 1.0 },
+        pos: (100.0, 100.0),
+    };
+    let difficulty = PlayDifficulty::from_settings(4.0, 9.0, 8.0, 5.0, Mods::default());
+    let mut relax = Autoplay::new(Mods::from_str("RX").unwrap(), &difficulty).unwrap();
+
+    // only clicks once the player moved the cursor onto the circle
+    assert!(relax.frame(1.01, &[target], Some((300.0, 300.0))).is_empty());
+    assert_eq!(relax.cursor(), (300.0, 300.0));
+    assert!(matches!(relax.frame(1.02, &[target], Some((105.0, 100.0)))[..], [EventLoopMsg::KeyPressed(_)]));
+}
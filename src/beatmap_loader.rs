@@ -0,0 +1,355 @@
+use crate::osu_parser::{parse_osu_file, OsuBeatMap, OsuBeatMapParseConfig};
+use std::fmt;
+use std::fs;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+const BUNDLED_OSU_MAP: &'static str = include_str!("Niko - Made of Fire (lesjuh) [Oni].osu");
+const BUNDLED_SONG: &'static str = "./assets/Niko - Made of Fire.mp3";
+
+const PARSE_CONFIG: OsuBeatMapParseConfig = OsuBeatMapParseConfig {
+    pre_add_audio_lead_in: true
+};
+
+#[derive(Debug)]
+pub enum BeatmapLoadError {
+    Io(std::io::Error),
+    Archive(zip::result::ZipError),
+    UnsupportedPath(PathBuf),
+    NoDifficulties(PathBuf),
+    UnknownDifficulty(String),
+    MissingFile(String),
+}
+
+impl fmt::Display for BeatmapLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BeatmapLoadError::Io(err) => write!(f, "I/O error: {}", err),
+            BeatmapLoadError::Archive(err) => write!(f, "Invalid .osz archive: {}", err),
+            BeatmapLoadError::UnsupportedPath(path) => {
+                write!(f, "{} is not a .osu file, .osz archive or a folder", path.display())
+            }
+            BeatmapLoadError::NoDifficulties(path) => {
+                write!(f, "No .osu difficulties found in {}", path.display())
+            }
+            BeatmapLoadError::UnknownDifficulty(name) => write!(f, "No difficulty named {}", name),
+            BeatmapLoadError::MissingFile(name) => write!(f, "Beatmap file {} is missing", name),
+        }
+    }
+}
+
+impl From<std::io::Error> for BeatmapLoadError {
+    fn from(err: std::io::Error) -> Self {
+        BeatmapLoadError::Io(err)
+    }
+}
+
+impl From<zip::result::ZipError> for BeatmapLoadError {
+    fn from(err: zip::result::ZipError) -> Self {
+        BeatmapLoadError::Archive(err)
+    }
+}
+
+// where the files of a beatmap set live
+enum BeatmapSetSource {
+    Folder(PathBuf),
+    Archive(PathBuf),
+}
+
+#[derive(Debug, Clone)]
+pub struct DifficultyEntry {
+    pub file_name: String,
+    pub version: String,
+}
+
+// a beatmap set (.osz archive, unpacked folder or a single .osu file)
+// and the difficulties it contains
+pub struct BeatmapSet {
+    source: BeatmapSetSource,
+    difficulties: Vec<DifficultyEntry>,
+}
+
+// custom hit sound samples shipped with the beatmap set,
+// `None` means the default sample from ./assets should be used
+#[derive(Debug, Default, Clone)]
+pub struct BeatmapSamples {
+    pub hit_normal: Option<PathBuf>,
+    pub hit_whistle: Option<PathBuf>,
+    pub hit_finish: Option<PathBuf>,
+    pub hit_clap: Option<PathBuf>,
+    pub slider_slide: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone)]
+pub struct LoadedBeatmap {
+    pub beatmap: OsuBeatMap,
+    // md5 of the .osu file, same as osu! uses to identify beatmaps
+    pub hash: String,
+    pub audio_path: PathBuf,
+    pub background_path: Option<PathBuf>,
+    pub samples: BeatmapSamples,
+}
+
+impl LoadedBeatmap {
+    // the map that ships with the executable
+    pub fn bundled() -> LoadedBeatmap {
+        LoadedBeatmap {
+            beatmap: parse_osu_file(BUNDLED_OSU_MAP.lines(), PARSE_CONFIG),
+            hash: beatmap_hash(BUNDLED_OSU_MAP),
+            audio_path: PathBuf::from(BUNDLED_SONG),
+            background_path: None,
+            samples: BeatmapSamples::default(),
+        }
+    }
+
+    // "Artist - Title [Version]"
+    pub fn label(&self) -> String {
+        format!("{} - {} [{}]", self.beatmap.artist, self.beatmap.title, self.beatmap.version)
+    }
+}
+
+impl BeatmapSet {
+    pub fn open(path: &Path) -> Result<BeatmapSet, BeatmapLoadError> {
+        let source;
+        let mut difficulties = Vec::new();
+
+        if path.is_dir() {
+            source = BeatmapSetSource::Folder(path.to_path_buf());
+            for file_name in source.file_names()? {
+                if has_extension(&file_name, "osu") {
+                    difficulties.push(source.difficulty_entry(file_name)?);
+                }
+            }
+        } else if has_extension(&path.to_string_lossy(), "osz") {
+            source = BeatmapSetSource::Archive(path.to_path_buf());
+            for file_name in source.file_names()? {
+                if has_extension(&file_name, "osu") {
+                    difficulties.push(source.difficulty_entry(file_name)?);
+                }
+            }
+        } else if has_extension(&path.to_string_lossy(), "osu") {
+            let folder = path.parent()
+                .map(Path::to_path_buf)
+                .unwrap_or_else(|| PathBuf::from("."));
+            let file_name = path.file_name()
+                .map(|it| it.to_string_lossy().to_string())
+                .ok_or_else(|| BeatmapLoadError::UnsupportedPath(path.to_path_buf()))?;
+            source = BeatmapSetSource::Folder(folder);
+            difficulties.push(source.difficulty_entry(file_name)?);
+        } else {
+            return Err(BeatmapLoadError::UnsupportedPath(path.to_path_buf()));
+        }
+
+        if difficulties.is_empty() {
+            return Err(BeatmapLoadError::NoDifficulties(path.to_path_buf()));
+        }
+
+        difficulties.sort_by(|a, b| a.file_name.cmp(&b.file_name));
+
+        Ok(BeatmapSet {
+            source,
+            difficulties,
+        })
+    }
+
+    pub fn difficulties(&self) -> &[DifficultyEntry] {
+        &self.difficulties
+    }
+
+    // loads the difficulty with the given version name (or the first one)
+    // and resolves the files it references
+    pub fn load(&self, difficulty: Option<&str>) -> Result<LoadedBeatmap, BeatmapLoadError> {
+        let entry = match difficulty {
+            Some(name) => self.difficulties
+                .iter()
+                .find(|it| it.version.eq_ignore_ascii_case(name) || it.file_name.eq_ignore_ascii_case(name))
+                .ok_or_else(|| BeatmapLoadError::UnknownDifficulty(name.to_string()))?,
+            None => &self.difficulties[0],
+        };
+
+        let text = self.source.read_to_string(&entry.file_name)?;
+        let beatmap = parse_osu_file(text.lines(), PARSE_CONFIG);
+
+        let audio_path = self.source.resolve(&beatmap.audio_file_name)?
+            .ok_or_else(|| BeatmapLoadError::MissingFile(beatmap.audio_file_name.clone()))?;
+
+        let background_path = match &beatmap.background_file_name {
+            Some(name) => self.source.resolve(name)?,
+            None => None,
+        };
+
+        let samples = {
+            let sample_set = beatmap.sample_set.to_lowercase();
+            let sample = |name: &str| self.source.resolve_sample(&format!("{}-{}", sample_set, name));
+            BeatmapSamples {
+                hit_normal: sample("hitnormal")?,
+                hit_whistle: sample("hitwhistle")?,
+                hit_finish: sample("hitfinish")?,
+                hit_clap: sample("hitclap")?,
+                slider_slide: sample("sliderslide")?,
+            }
+        };
+
+        log::info!("Loaded {} - {} [{}]", beatmap.artist, beatmap.title, beatmap.version);
+
+        Ok(LoadedBeatmap {
+            beatmap,
+            hash: beatmap_hash(&text),
+            audio_path,
+            background_path,
+            samples,
+        })
+    }
+}
+
+impl BeatmapSetSource {
+    fn file_names(&self) -> Result<Vec<String>, BeatmapLoadError> {
+        let mut names = Vec::new();
+        match self {
+            BeatmapSetSource::Folder(folder) => {
+                for dir_entry in fs::read_dir(folder)? {
+                    let dir_entry = dir_entry?;
+                    if dir_entry.file_type()?.is_file() {
+                        names.push(dir_entry.file_name().to_string_lossy().to_string());
+                    }
+                }
+            }
+            BeatmapSetSource::Archive(archive_path) => {
+                let mut archive = zip::ZipArchive::new(File::open(archive_path)?)?;
+                for i in 0..archive.len() {
+                    let file = archive.by_index(i)?;
+                    if !file.is_dir() {
+                        names.push(file.name().to_string());
+                    }
+                }
+            }
+        }
+        Ok(names)
+    }
+
+    fn difficulty_entry(&self, file_name: String) -> Result<DifficultyEntry, BeatmapLoadError> {
+        let text = self.read_to_string(&file_name)?;
+        let version = text.lines()
+            .find(|line| line.starts_with("Version:"))
+            .map(|line| line["Version:".len()..].trim().to_string())
+            .unwrap_or_else(|| file_name.clone());
+        Ok(DifficultyEntry {
+            file_name,
+            version,
+        })
+    }
+
+    fn read_to_string(&self, file_name: &str) -> Result<String, BeatmapLoadError> {
+        let mut text = String::new();
+        match self {
+            BeatmapSetSource::Folder(folder) => {
+                File::open(folder.join(file_name))?.read_to_string(&mut text)?;
+            }
+            BeatmapSetSource::Archive(archive_path) => {
+                let mut archive = zip::ZipArchive::new(File::open(archive_path)?)?;
+                archive.by_name(file_name)?.read_to_string(&mut text)?;
+            }
+        }
+        Ok(text)
+    }
+
+    // finds a file by name (case insensitive, beatmaps are mostly made on windows)
+    // and returns a path to it on disk. Archive entries get extracted to a cache folder first.
+    fn resolve(&self, file_name: &str) -> Result<Option<PathBuf>, BeatmapLoadError> {
+        let found = self.file_names()?
+            .into_iter()
+            .find(|it| it.replace('\\', "/").eq_ignore_ascii_case(&file_name.replace('\\', "/")));
+
+        let found = match found {
+            Some(found) => found,
+            None => return Ok(None),
+        };
+
+        match self {
+            BeatmapSetSource::Folder(folder) => Ok(Some(folder.join(found))),
+            BeatmapSetSource::Archive(archive_path) => {
+                let target = match archive_entry_path(&found) {
+                    Some(entry_path) => archive_cache_folder(archive_path)?.join(entry_path),
+                    None => return Ok(None),
+                };
+
+                if !target.exists() {
+                    if let Some(parent) = target.parent() {
+                        fs::create_dir_all(parent)?;
+                    }
+                    let mut archive = zip::ZipArchive::new(File::open(archive_path)?)?;
+                    let mut file = archive.by_name(&found)?;
+                    let mut out = File::create(&target)?;
+                    std::io::copy(&mut file, &mut out)?;
+                }
+
+                Ok(Some(target))
+            }
+        }
+    }
+
+    fn resolve_sample(&self, name: &str) -> Result<Option<PathBuf>, BeatmapLoadError> {
+        match self.resolve(&format!("{}.wav", name))? {
+            Some(path) => Ok(Some(path)),
+            None => self.resolve(&format!("{}.ogg", name)),
+        }
+    }
+}
+
+fn beatmap_hash(osu_file: &str) -> String {
+    format!("{:x}", md5::compute(osu_file))
+}
+
+// keyed by the modification time too, so a re-downloaded archive with the same name
+// doesn't pick up files extracted from the old one
+fn archive_cache_folder(archive_path: &Path) -> Result<PathBuf, BeatmapLoadError> {
+    let archive_name = archive_path.file_stem()
+        .map(|it| it.to_string_lossy().to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+    let modified_at = fs::metadata(archive_path)?
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map(|it| it.as_secs())
+        .unwrap_or(0);
+    Ok(std::env::temp_dir()
+        .join("rusty-osu")
+        .join(format!("{}-{}", archive_name, modified_at)))
+}
+
+// the entry path relative to the cache folder, None for entries that would escape it
+fn archive_entry_path(entry_name: &str) -> Option<PathBuf> {
+    let mut path = PathBuf::new();
+    for part in entry_name.split(|c| c == '/' || c == '\\') {
+        match part {
+            "" | "." => {}
+            ".." => return None,
+            part if part.contains(':') => return None,
+            part => path.push(part),
+        }
+    }
+    if path.as_os_str().is_empty() { None } else { Some(path) }
+}
+
+fn has_extension(file_name: &str, extension: &str) -> bool {
+    Path::new(file_name)
+        .extension()
+        .map(|it| it.to_string_lossy().eq_ignore_ascii_case(extension))
+        .unwrap_or(false)
+}
+
+#[test]
+fn has_extension_test() {
+    assert!(has_extension("Niko - Made of Fire (lesjuh) [Oni].osu", "osu"));
+    assert!(has_extension("set.OSZ", "osz"));
+    assert!(!has_extension("maid-bg.png", "osu"));
+}
+
+#[test]
+fn archive_entry_path_test() {
+    assert_eq!(archive_entry_path("sb/bg.png"), Some(PathBuf::from("sb").join("bg.png")));
+    assert_eq!(archive_entry_path("sb\\hitnormal.wav"), Some(PathBuf::from("sb").join("hitnormal.wav")));
+    assert_eq!(archive_entry_path("../escape.png"), None);
+    assert_eq!(archive_entry_path("C:/escape.png"), None);
+}
@@ -0,0 +1,121 @@
+use crate::mods::Mods;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "rusty-osu")]
+pub struct CliArgs {
+    /// Path to a .osu file, a .osz archive or an unpacked beatmap folder.
+    /// Plays the bundled map when omitted.
+    #[structopt(parse(from_os_str))]
+    pub beatmap: Option<PathBuf>,
+
+    /// Difficulty (version) name to play, defaults to the first one in the set
+    #[structopt(short, long)]
+    pub difficulty: Option<String>,
+
+    /// Print the difficulties of the beatmap set and exit
+    #[structopt(long)]
+    pub list_difficulties: bool,
+
+    /// Mods to play with, eg. "HDHR" or "HD,DT"
+    #[structopt(short, long, default_value = "")]
+    pub mods: Mods,
+
+    /// Universal audio offset in milliseconds for this session,
+    /// positive values make objects appear later. Defaults to the saved one.
+    #[structopt(long, allow_hyphen_values = true)]
+    pub offset: Option<i32>,
+
+    /// Save a local audio offset (in milliseconds) for the selected beatmap
+    #[structopt(long, allow_hyphen_values = true)]
+    pub local_offset: Option<i32>,
+
+    /// Start the offset calibration instead of playing
+    #[structopt(long)]
+    pub calibrate: bool,
+
+    /// Window size, eg. "1280x720"
+    #[structopt(long)]
+    pub window_size: Option<WindowSize>,
+
+    /// Start in borderless fullscreen
+    #[structopt(long)]
+    pub fullscreen: bool,
+
+    /// Watch a replay recorded on the given beatmap, its mods replace --mods
+    #[structopt(long, parse(from_os_str))]
+    pub replay: Option<PathBuf>,
+
+    /// Let the game play the map by itself
+    #[structopt(long)]
+    pub autoplay: bool,
+
+    /// Difficulty adjust, overrides the circle size of the map (0-11)
+    #[structopt(long)]
+    pub cs: Option<f64>,
+
+    /// Difficulty adjust, overrides the approach rate of the map (0-11)
+    #[structopt(long)]
+    pub ar: Option<f64>,
+
+    /// Difficulty adjust, overrides the overall difficulty of the map (0-11)
+    #[structopt(long)]
+    pub od: Option<f64>,
+
+    /// Difficulty adjust, overrides the hp drain rate of the map (0-11)
+    #[structopt(long)]
+    pub hp: Option<f64>,
+
+    /// Difficulty adjust, song rate from 0.5 to 2.0 in steps of 0.05.
+    /// Replaces the rate of DT/NC/HT
+    #[structopt(long)]
+    pub rate: Option<f64>,
+
+    /// Score with ScoreV2 (as used in tournaments) instead of ScoreV1
+    #[structopt(long)]
+    pub score_v2: bool,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct WindowSize {
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug)]
+pub struct WindowSizeParseError(String);
+
+impl fmt::Display for WindowSizeParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Expected a window size like 1280x720, got {}", self.0)
+    }
+}
+
+impl FromStr for WindowSize {
+    type Err = WindowSizeParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || WindowSizeParseError(s.to_string());
+        let mut parts = s.splitn(2, |c| c == 'x' || c == 'X');
+        let width = parts.next()
+            .and_then(|it| u32::from_str(it.trim()).ok())
+            .ok_or_else(err)?;
+        let height = parts.next()
+            .and_then(|it| u32::from_str(it.trim()).ok())
+            .ok_or_else(err)?;
+        Ok(WindowSize {
+            width,
+            height,
+        })
+    }
+}
+
+#[test]
+fn window_size_parse_test() {
+    let size = WindowSize::from_str("1280x720").unwrap();
+    assert_eq!((size.width, size.height), (1280, 720));
+    assert!(WindowSize::from_str("1280").is_err());
+}
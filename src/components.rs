@@ -1,5 +1,6 @@
 use specs::{Component, VecStorage};
-use crate::consts::{HIT_RANGE, PERFECT_HIT_RANGE};
+use crate::consts::SLIDER_TICK_END_GAP;
+use crate::difficulty::HitWindows;
 use std::time::{Instant};
 use crate::osu_parser::OsuHitObjectHitSound;
 
@@ -14,18 +15,34 @@ pub struct Circle;
 #[derive(Debug, Copy, Clone)]
 pub struct Lifetime {
     pub remaining: f64,
+    // gameplay time at which the object should be hit
+    pub hit_at: f64,
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq, PartialOrd)]
 pub enum CircleHitRating {
     MISS,
+    MEH,
     GOOD,
     GREAT,
 }
 
+impl CircleHitRating {
+    pub fn hit_value(&self) -> u64 {
+        match self {
+            CircleHitRating::MISS => 0,
+            CircleHitRating::MEH => 50,
+            CircleHitRating::GOOD => 100,
+            CircleHitRating::GREAT => 300,
+        }
+    }
+}
+
 pub struct DespawnObject {
     pub reason: DespawnObjectReason,
     pub despawned_at: Instant,
+    // signed distance to the perfect hit time in seconds, negative when early
+    pub hit_error: Option<f64>,
 }
 
 pub struct HitSound {
@@ -34,16 +51,132 @@ pub struct HitSound {
 
 pub enum DespawnObjectReason {
     CircleHit(CircleHitRating),
-    SliderEnd(SliderState),
+    SliderEnd(CircleHitRating),
+    SpinnerEnd(CircleHitRating),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Judgement {
+    Rating(CircleHitRating),
+    SliderBreak,
+}
+
+// clicked while it couldn't be hit yet
+pub struct Shake {
+    pub started_at: f64,
+}
+
+// short lived feedback drawn where an object got judged
+pub struct JudgementPopup {
+    pub judgement: Judgement,
+    pub spawned_at: f64,
 }
 
 pub struct Slider {
     pub curve_points: Vec<(f32, f32)>,
     pub skia_path: skia_safe::Path,
+    // all slides (repeats) included
     pub duration_in_secs: f64,
+    pub slides: i32,
     pub progress: f64,
     pub curve: SliderCurve,
     pub state: SliderState,
+    pub checkpoints: Vec<SliderCheckpoint>,
+    pub next_checkpoint: usize,
+    pub checkpoints_hit: usize,
+    pub head_pos: (f32, f32),
+    // None until the head got clicked or missed
+    pub head: Option<CircleHitRating>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, PartialOrd)]
+pub enum SliderCheckpointKind {
+    Tick,
+    Repeat,
+    End,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct SliderCheckpoint {
+    // secs since the slider start
+    pub at: f64,
+    pub kind: SliderCheckpointKind,
+}
+
+// ticks are placed every `tick_interval` secs along each slide,
+// reversed slides have them mirrored
+pub fn slider_checkpoints(slide_duration: f64, slides: i32, tick_interval: f64) -> Vec<SliderCheckpoint> {
+    let mut checkpoints = Vec::new();
+    for slide in 0..slides {
+        let slide_start = slide as f64 * slide_duration;
+        let mut tick = tick_interval;
+        while tick_interval > 0.0 && tick < slide_duration - SLIDER_TICK_END_GAP {
+            let at = if slide % 2 == 0 { tick } else { slide_duration - tick };
+            checkpoints.push(SliderCheckpoint { at: slide_start + at, kind: SliderCheckpointKind::Tick });
+            tick += tick_interval;
+        }
+        let kind = if slide == slides - 1 { SliderCheckpointKind::End } else { SliderCheckpointKind::Repeat };
+        checkpoints.push(SliderCheckpoint { at: slide_start + slide_duration, kind });
+    }
+    checkpoints.sort_by(|a, b| a.at.partial_cmp(&b.at).unwrap());
+    checkpoints
+}
+
+#[test]
+fn slider_checkpoints_test() {
+    let checkpoints = slider_checkpoints(1.0, 2, 0.4);
+    let kinds = checkpoints.iter().map(|it| it.kind).collect::<Vec<_>>();
+    assert_eq!(kinds, vec![
+        SliderCheckpointKind::Tick,
+        SliderCheckpointKind::Tick,
+        SliderCheckpointKind::Repeat,
+        SliderCheckpointKind::Tick,
+        SliderCheckpointKind::Tick,
+        SliderCheckpointKind::End,
+    ]);
+    assert!((checkpoints[3].at - 1.2).abs() < 1e-9);
+    assert!((checkpoints[4].at - 1.6).abs() < 1e-9);
+}
+
+pub struct Spinner {
+    pub end_at: f64,
+    pub required_spins: u32,
+    // radians spun in total, either direction counts
+    pub rotation: f64,
+    pub last_angle: Option<f64>,
+    pub spins: u32,
+}
+
+impl Spinner {
+    pub fn new(start_at: f64, end_at: f64, overall_difficulty: f64) -> Self {
+        // same spin speed osu!lazer asks for
+        let spins_per_sec = if overall_difficulty > 5.0 {
+            2.0 + 0.1 * (overall_difficulty - 5.0)
+        } else {
+            2.0 - 0.1 * (5.0 - overall_difficulty)
+        };
+        Self {
+            end_at,
+            required_spins: ((end_at - start_at).max(0.0) * spins_per_sec) as u32,
+            rotation: 0.0,
+            last_angle: None,
+            spins: 0,
+        }
+    }
+
+    pub fn rating(&self) -> CircleHitRating {
+        if self.spins >= self.required_spins {
+            return CircleHitRating::GREAT;
+        }
+        let completed = self.spins as f64 / self.required_spins as f64;
+        if completed >= 0.75 {
+            CircleHitRating::GOOD
+        } else if completed >= 0.25 {
+            CircleHitRating::MEH
+        } else {
+            CircleHitRating::MISS
+        }
+    }
 }
 
 pub enum SliderStateChange {
@@ -99,6 +232,48 @@ impl Slider {
 
         return result;
     }
+
+    pub fn rating(&self) -> CircleHitRating {
+        let head_hit = matches!(self.head, Some(rating) if rating != CircleHitRating::MISS);
+        slider_rating(head_hit, self.checkpoints_hit, self.checkpoints.len())
+    }
+
+    // 0.0 - 1.0 position along the curve, `elapsed` being secs since the slider start
+    pub fn curve_position(&self, elapsed: f64) -> f64 {
+        let slides = self.slides.max(1);
+        let slide_duration = self.duration_in_secs / slides as f64;
+        let slide = (elapsed / slide_duration).floor()
+            .max(0.0)
+            .min((slides - 1) as f64);
+        let position = (elapsed / slide_duration - slide).min(1.0);
+        if slide as i32 % 2 == 1 { 1.0 - position } else { position }
+    }
+}
+
+// osu! stable, judged by how many of the head, ticks, repeats and the tail were hit
+pub fn slider_rating(head_hit: bool, checkpoints_hit: usize, checkpoint_count: usize) -> CircleHitRating {
+    let total = checkpoint_count + 1;
+    let hit = checkpoints_hit + if head_hit { 1 } else { 0 };
+    if hit >= total {
+        CircleHitRating::GREAT
+    } else if hit * 2 >= total {
+        CircleHitRating::GOOD
+    } else if hit > 0 {
+        CircleHitRating::MEH
+    } else {
+        CircleHitRating::MISS
+    }
+}
+
+#[test]
+fn slider_rating_test() {
+    assert_eq!(slider_rating(true, 3, 3), CircleHitRating::GREAT);
+    assert_eq!(slider_rating(false, 3, 3), CircleHitRating::GOOD);
+    assert_eq!(slider_rating(true, 1, 3), CircleHitRating::GOOD);
+    assert_eq!(slider_rating(false, 1, 3), CircleHitRating::MEH);
+    assert_eq!(slider_rating(false, 0, 3), CircleHitRating::MISS);
+    // a slider without ticks or repeats still has its tail
+    assert_eq!(slider_rating(false, 1, 1), CircleHitRating::GOOD);
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -136,18 +311,15 @@ pub struct SliderCurveCubicBezier {
 
 impl Lifetime {
     pub fn zero() -> &'static Lifetime {
-        const INSTANCE: Lifetime = Lifetime { remaining: 0.0 };
+        const INSTANCE: Lifetime = Lifetime { remaining: 0.0, hit_at: 0.0 };
         return &INSTANCE;
     }
     pub fn is_dead(&self) -> bool { self.remaining <= 0.0 }
     pub fn is_alive(&self) -> bool {
         self.remaining > 0.0
     }
-    pub fn is_in_hit_zone(&self) -> bool {
-        HIT_RANGE.contains(&self.remaining)
-    }
-    pub fn is_in_perfect_hit_zone(&self) -> bool {
-        PERFECT_HIT_RANGE.contains(&self.remaining)
+    pub fn is_in_hit_zone(&self, hit_windows: &HitWindows) -> bool {
+        self.remaining.abs() < hit_windows.meh
     }
 }
 
@@ -179,6 +351,18 @@ impl Component for Slider {
     type Storage = VecStorage<Slider>;
 }
 
+impl Component for Spinner {
+    type Storage = VecStorage<Spinner>;
+}
+
+impl Component for Shake {
+    type Storage = VecStorage<Shake>;
+}
+
+impl Component for JudgementPopup {
+    type Storage = VecStorage<JudgementPopup>;
+}
+
 impl Default for CircleHitRating {
     fn default() -> Self {
         CircleHitRating::MISS
@@ -1,15 +1,46 @@
-use std::ops::Range;
 use std::time::Duration;
 
 pub const DURATION_ZERO: Duration = Duration::from_nanos(0);
+// circles are drawn at this size, then scaled to the circle size of the map
 pub const BASE_CIRCLE_RADIUS: f32 = 35.0;
 pub const BASE_CIRCLE_DIAMETER: f32 = BASE_CIRCLE_RADIUS * 2.0;
 pub const BASE_SLIDER_CIRCLE_RADIUS: f32 = 60.0;
+// spinners are always in the middle of the playfield
+pub const SPINNER_CENTER: (f32, f32) = (256.0, 192.0);
+pub const SPINNER_RADIUS: f32 = 180.0;
 
-pub const LIFETIME: f64 = 0.700;
-pub const HIT_WINDOW: f64 = 0.200;
-pub const HIT_RANGE: Range<f64> = -HIT_WINDOW..HIT_WINDOW;
-pub const PERFECT_HIT_RANGE: Range<f64> = -(HIT_WINDOW / 3.0)..(HIT_WINDOW / 3.0);
+// clicks this early count as a miss, even earlier ones only shake the circle
+pub const EARLY_MISS_WINDOW: f64 = 0.400;
 pub const TRIAL_POINTS: usize = 32;
 pub const TRAIL_SAMPLE_EACH: Duration = Duration::from_millis(10);
+// hit error meter below the playfield
+pub const HIT_ERROR_METER_TICKS: usize = 30;
+pub const HIT_ERROR_TICK_LIFETIME: f64 = 4.0;
+// hidden, as parts of the approach time left until the hit
+pub const HIDDEN_FADE_OUT_START: f64 = 0.6;
+pub const HIDDEN_FADE_OUT_END: f64 = 0.3;
+// flashlight radius in osu! pixels, shrinking at 100 and 200 combo
+pub const FLASHLIGHT_RADII: [f32; 3] = [180.0, 140.0, 100.0];
+pub const JUDGEMENT_POPUP_LIFETIME: f64 = 0.600;
+// time between the last object and the results screen
+pub const MAP_END_DELAY: f64 = 2.0;
+// replay playback, seeking jumps by this much and fast forwards through the recorded frames,
+// in steps of gameplay time where nothing was recorded
+pub const REPLAY_SEEK_JUMP: f64 = 5.0;
+pub const REPLAY_SEEK_STEP: f64 = 0.050;
+pub const REPLAY_SPEEDS: [f64; 6] = [0.25, 0.5, 0.75, 1.0, 1.5, 2.0];
+// wall time per frame that playback may spend on recorded frames it has fallen behind on
+pub const REPLAY_CATCH_UP_BUDGET: Duration = Duration::from_millis(5);
+// slider ticks closer than this to the end of a slide are skipped
+pub const SLIDER_TICK_END_GAP: f64 = 0.010;
 
+// gameplay clock vs. song position syncing
+pub const AUDIO_STALL_THRESHOLD: f64 = 0.200;
+pub const AUDIO_RESYNC_THRESHOLD: f64 = 0.050;
+pub const AUDIO_DRIFT_CORRECTION: f64 = 0.1;
+// time stretched songs are resampled to this
+pub const STRETCH_SAMPLE_RATE: u32 = 44100;
+
+// offset calibration metronome
+pub const CALIBRATION_BEAT_INTERVAL: f64 = 0.500;
+pub const CALIBRATION_MIN_TAPS: usize = 8;
@@ -0,0 +1,265 @@
+use std::fmt;
+use crate::components::CircleHitRating;
+use crate::mods::{Mod, Mods};
+use crate::osu_parser::OsuBeatMap;
+
+// see https://osu.ppy.sh/wiki/en/Gameplay/Game_modifier/Hard_Rock
+const HARD_ROCK_CS_MULTIPLIER: f64 = 1.3;
+const HARD_ROCK_MULTIPLIER: f64 = 1.4;
+const EASY_MULTIPLIER: f64 = 0.5;
+// easy refills the hp bar this many times before failing
+const EASY_EXTRA_LIVES: u32 = 2;
+// difficulty adjust limits, a bit past 10 for practice
+const ADJUST_MAX_SETTING: f64 = 11.0;
+const ADJUST_MIN_RATE: f64 = 0.5;
+const ADJUST_MAX_RATE: f64 = 2.0;
+const ADJUST_RATE_STEP: f64 = 0.05;
+
+// half widths in secs, a hit is rated by the smallest window it falls into
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct HitWindows {
+    pub great: f64,
+    pub good: f64,
+    pub meh: f64,
+}
+
+impl HitWindows {
+    pub fn from_od(od: f64) -> Self {
+        Self {
+            great: (80.0 - 6.0 * od) / 1000.0,
+            good: (140.0 - 8.0 * od) / 1000.0,
+            meh: (200.0 - 10.0 * od) / 1000.0,
+        }
+    }
+
+    // `hit_error` is negative when early, None when outside of all windows
+    pub fn rating(&self, hit_error: f64) -> Option<CircleHitRating> {
+        let error = hit_error.abs();
+        if error < self.great {
+            Some(CircleHitRating::GREAT)
+        } else if error < self.good {
+            Some(CircleHitRating::GOOD)
+        } else if error < self.meh {
+            Some(CircleHitRating::MEH)
+        } else {
+            None
+        }
+    }
+}
+
+// the map difficulty with mods applied, times are song time
+// so speed mods shrink them in real time
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PlayDifficulty {
+    pub circle_size: f64,
+    pub approach_rate: f64,
+    pub overall_difficulty: f64,
+    pub hp_drain_rate: f64,
+    // secs an object is visible before it has to be hit
+    pub preempt: f64,
+    pub hit_windows: HitWindows,
+    // in osu! pixels
+    pub circle_radius: f32,
+    pub lives: u32,
+}
+
+impl PlayDifficulty {
+    pub fn new(beatmap: &OsuBeatMap, mods: Mods) -> Self {
+        Self::from_settings(
+            beatmap.circle_size,
+            beatmap.approach_rate,
+            beatmap.overall_difficulty,
+            beatmap.hp_drain_rate,
+            mods,
+        )
+    }
+
+    pub fn from_settings(cs: f64, ar: f64, od: f64, hp: f64, mods: Mods) -> Self {
+        let (cs, ar, od, hp) = if mods.contains(Mod::HardRock) {
+            (
+                (cs * HARD_ROCK_CS_MULTIPLIER).min(10.0),
+                (ar * HARD_ROCK_MULTIPLIER).min(10.0),
+                (od * HARD_ROCK_MULTIPLIER).min(10.0),
+                (hp * HARD_ROCK_MULTIPLIER).min(10.0),
+            )
+        } else if mods.contains(Mod::Easy) {
+            (cs * EASY_MULTIPLIER, ar * EASY_MULTIPLIER, od * EASY_MULTIPLIER, hp * EASY_MULTIPLIER)
+        } else {
+            (cs, ar, od, hp)
+        };
+
+        Self {
+            circle_size: cs,
+            approach_rate: ar,
+            overall_difficulty: od,
+            hp_drain_rate: hp,
+            preempt: preempt(ar),
+            hit_windows: HitWindows::from_od(od),
+            circle_radius: (54.4 - 4.48 * cs) as f32,
+            lives: if mods.contains(Mod::Easy) { EASY_EXTRA_LIVES } else { 0 },
+        }
+    }
+
+    // hp lost on a miss, a full bar being 1.0
+    pub fn miss_drain(&self) -> f64 {
+        0.05 + 0.015 * self.hp_drain_rate
+    }
+}
+
+// custom overrides for the map settings and the song rate, applied to the parsed map
+// before the other mods
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct DifficultyAdjust {
+    pub circle_size: Option<f64>,
+    pub approach_rate: Option<f64>,
+    pub overall_difficulty: Option<f64>,
+    pub hp_drain_rate: Option<f64>,
+    pub rate: Option<f64>,
+}
+
+#[derive(Debug)]
+pub struct DifficultyAdjustError(String);
+
+impl fmt::Display for DifficultyAdjustError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl DifficultyAdjust {
+    pub fn new(
+        circle_size: Option<f64>,
+        approach_rate: Option<f64>,
+        overall_difficulty: Option<f64>,
+        hp_drain_rate: Option<f64>,
+        rate: Option<f64>,
+    ) -> Result<Self, DifficultyAdjustError> {
+        let settings = [("CS", circle_size), ("AR", approach_rate), ("OD", overall_difficulty), ("HP", hp_drain_rate)];
+        for (name, value) in settings.iter() {
+            if let Some(value) = value {
+                if !(0.0..=ADJUST_MAX_SETTING).contains(value) {
+                    return Err(DifficultyAdjustError(format!("{} has to be between 0 and {}, got {}", name, ADJUST_MAX_SETTING, value)));
+                }
+            }
+        }
+
+        // snapped to the closest step
+        let rate = match rate {
+            Some(rate) if (ADJUST_MIN_RATE..=ADJUST_MAX_RATE).contains(&rate) => {
+                Some((rate / ADJUST_RATE_STEP).round() * ADJUST_RATE_STEP)
+            }
+            Some(rate) => {
+                return Err(DifficultyAdjustError(format!("Rate has to be between {} and {}, got {}", ADJUST_MIN_RATE, ADJUST_MAX_RATE, rate)));
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            circle_size,
+            approach_rate,
+            overall_difficulty,
+            hp_drain_rate,
+            rate,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == DifficultyAdjust::default()
+    }
+
+    pub fn apply(&self, beatmap: &mut OsuBeatMap) {
+        if let Some(circle_size) = self.circle_size {
+            beatmap.circle_size = circle_size;
+        }
+        if let Some(approach_rate) = self.approach_rate {
+            beatmap.approach_rate = approach_rate;
+        }
+        if let Some(overall_difficulty) = self.overall_difficulty {
+            beatmap.overall_difficulty = overall_difficulty;
+        }
+        if let Some(hp_drain_rate) = self.hp_drain_rate {
+            beatmap.hp_drain_rate = hp_drain_rate;
+        }
+    }
+
+    // a custom rate replaces the one from the speed mods
+    pub fn rate(&self, mods: Mods) -> f64 {
+        self.rate.unwrap_or_else(|| mods.rate())
+    }
+}
+
+impl fmt::Display for DifficultyAdjust {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let settings = [
+            ("CS", self.circle_size),
+            ("AR", self.approach_rate),
+            ("OD", self.overall_difficulty),
+            ("HP", self.hp_drain_rate),
+        ];
+        let mut parts = settings.iter()
+            .filter_map(|(name, value)| value.map(|value| format!("{}{}", name, value)))
+            .collect::<Vec<_>>();
+        if let Some(rate) = self.rate {
+            parts.push(format!("{:.2}x", rate));
+        }
+        write!(f, "DA({})", parts.join(" "))
+    }
+}
+
+pub fn preempt(ar: f64) -> f64 {
+    let millis = if ar < 5.0 {
+        1200.0 + 600.0 * (5.0 - ar) / 5.0
+    } else {
+        1200.0 - 750.0 * (ar - 5.0) / 5.0
+    };
+    millis / 1000.0
+}
+
+#[test]
+fn preempt_test() {
+    assert!((preempt(0.0) - 1.8).abs() < 1e-9);
+    assert!((preempt(5.0) - 1.2).abs() < 1e-9);
+    assert!((preempt(9.0) - 0.6).abs() < 1e-9);
+    assert!((preempt(10.0) - 0.45).abs() < 1e-9);
+}
+
+#[test]
+fn hit_windows_test() {
+    let windows = HitWindows::from_od(5.0);
+    assert!((windows.great - 0.050).abs() < 1e-9);
+    assert!((windows.good - 0.100).abs() < 1e-9);
+    assert!((windows.meh - 0.150).abs() < 1e-9);
+    assert_eq!(windows.rating(-0.049), Some(CircleHitRating::GREAT));
+    assert_eq!(windows.rating(0.099), Some(CircleHitRating::GOOD));
+    assert_eq!(windows.rating(-0.149), Some(CircleHitRating::MEH));
+    assert_eq!(windows.rating(0.150), None);
+}
+
+#[test]
+fn play_difficulty_mods_test() {
+    use std::str::FromStr;
+
+    let hard_rock = PlayDifficulty::from_settings(4.0, 9.0, 8.0, 6.0, Mods::from_str("HR").unwrap());
+    assert!((hard_rock.circle_size - 5.2).abs() < 1e-9);
+    assert_eq!(hard_rock.approach_rate, 10.0);
+    assert_eq!(hard_rock.overall_difficulty, 10.0);
+    assert!((hard_rock.hp_drain_rate - 8.4).abs() < 1e-9);
+    assert_eq!(hard_rock.lives, 0);
+
+    let easy = PlayDifficulty::from_settings(4.0, 9.0, 8.0, 6.0, Mods::from_str("EZ").unwrap());
+    assert_eq!(easy.circle_size, 2.0);
+    assert_eq!(easy.approach_rate, 4.5);
+    assert_eq!(easy.lives, EASY_EXTRA_LIVES);
+}
+
+#[test]
+fn difficulty_adjust_test() {
+    let adjust = DifficultyAdjust::new(None, Some(10.3), None, None, Some(1.33)).unwrap();
+    assert!((adjust.rate.unwrap() - 1.35).abs() < 1e-9);
+    assert_eq!(adjust.rate(Mods::default()), adjust.rate.unwrap());
+    assert_eq!(adjust.to_string(), "DA(AR10.3 1.35x)");
+
+    assert!(DifficultyAdjust::new(Some(12.0), None, None, None, None).is_err());
+    assert!(DifficultyAdjust::new(None, None, None, None, Some(3.0)).is_err());
+    assert!(DifficultyAdjust::new(None, None, None, None, None).unwrap().is_empty());
+}
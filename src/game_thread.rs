@@ -1,13 +1,18 @@
-use crate::components::*;
-use crate::rendering_system::RenderingSystem;
+use crate::beatmap_loader::LoadedBeatmap;
+use crate::difficulty::DifficultyAdjust;
+use crate::mods::Mods;
+use crate::replay::Replay;
+use crate::scoring::ScoringMode;
+use crate::settings::Settings;
 use crate::resources::*;
-use crate::systems::*;
+use crate::scenes::{calibration, gameplay, main_menu, results, settings_menu, song_select};
 use glutin::event::VirtualKeyCode;
 use glutin::window::Window;
 use glutin::{ContextWrapper, NotCurrent, PossiblyCurrent, WindowedContext};
 use skia_safe::gpu::Context as GpuContext;
-use specs::{Dispatcher, DispatcherBuilder, World, WorldExt};
+use specs::{Dispatcher, World, WorldExt};
 use std::cell::RefCell;
+use std::path::PathBuf;
 use std::rc::Rc;
 use std::sync::mpsc::{Receiver, Sender};
 use std::time::{Duration, Instant};
@@ -25,7 +30,100 @@ pub enum GameThreadMsg {
     Quit,
 }
 
+// everything the game thread needs to know to start playing
+#[derive(Clone)]
+pub struct GameConfig {
+    pub beatmap: LoadedBeatmap,
+    pub mods: Mods,
+    pub difficulty_adjust: DifficultyAdjust,
+    pub scoring_mode: ScoringMode,
+    // in seconds, positive values delay the hit objects
+    pub audio_offset: f64,
+    // watching this replay instead of playing
+    pub replay: Option<Replay>,
+}
+
+// options picked on the command line, they last for the whole session
+pub struct Session {
+    pub settings: Settings,
+    pub mods: Mods,
+    pub difficulty_adjust: DifficultyAdjust,
+    pub scoring_mode: ScoringMode,
+    // universal offset in ms from --offset, never saved
+    pub offset_override_ms: Option<i32>,
+    pub songs_folder: PathBuf,
+    // beatmap set given on the command line, listed in song select too
+    pub extra_beatmap_set: Option<PathBuf>,
+}
+
+// shared by all scenes
+pub struct SceneContext {
+    pub window_ctx: Rc<ContextWrapper<PossiblyCurrent, Window>>,
+    pub gpu_context: Rc<RefCell<GpuContext>>,
+    pub session: Session,
+}
+
+pub enum NextScene {
+    MainMenu,
+    SongSelect,
+    Gameplay(GameConfig),
+    Results(PlayResult, GameConfig),
+    Settings,
+    OffsetCalibration,
+    Quit,
+}
+
+// systems request a hand off to another scene through this resource,
+// the game thread swaps scenes once the current frame is done
+#[derive(Default)]
+pub struct SceneChange(pub Option<NextScene>);
+
+impl SceneChange {
+    pub fn request(&mut self, next: NextScene) {
+        self.0 = Some(next);
+    }
+}
+
+// a screen of the game (menu, gameplay, results ...)
+pub trait Scene {
+    fn world(&mut self) -> &mut World;
+    fn setup(&mut self);
+    fn run_frame(&mut self);
+}
+
+// a scene running all of its systems through a single dispatcher
+pub struct DispatcherScene {
+    world: World,
+    dispatcher: Dispatcher<'static, 'static>,
+}
+
+impl DispatcherScene {
+    pub fn new(world: World, dispatcher: Dispatcher<'static, 'static>) -> Self {
+        Self {
+            world,
+            dispatcher,
+        }
+    }
+}
+
+impl Scene for DispatcherScene {
+    fn world(&mut self) -> &mut World {
+        &mut self.world
+    }
+
+    fn setup(&mut self) {
+        self.dispatcher.setup(&mut self.world);
+    }
+
+    fn run_frame(&mut self) {
+        self.dispatcher.dispatch(&self.world);
+        self.world.maintain();
+    }
+}
+
 pub fn game_thread(
+    session: Session,
+    start_scene: NextScene,
     window_ctx: WindowedContext<NotCurrent>,
     event_loop_msg_rec: Receiver<EventLoopMsg>,
     game_thread_sender: Sender<GameThreadMsg>,
@@ -41,24 +139,51 @@ pub fn game_thread(
             Rc::new(RefCell::new(ctx))
         };
 
-        let (mut world, mut game_dispatcher) =
-            make_gameplay_world(window_ctx.clone(), gpu_context.clone());
+        let mut scene_ctx = SceneContext {
+            window_ctx: window_ctx.clone(),
+            gpu_context,
+            session,
+        };
+
+        let mut scene = match make_scene(start_scene, &scene_ctx) {
+            Some(scene) => scene,
+            None => return game_thread_sender.send(GameThreadMsg::Quit).unwrap(),
+        };
 
         let render_each = Duration::from_micros(1380); // 720 fps
         let mut started_at;
         let mut elapsed = render_each;
         let mut exit = false;
 
-        game_dispatcher.setup(&mut world);
+        scene.setup();
 
         loop {
             started_at = Instant::now();
-            update_time(&mut world, elapsed);
-            game_dispatcher.dispatch(&mut world);
-            world.maintain();
+            update_time(scene.world(), elapsed);
+            scene.run_frame();
             window_ctx.swap_buffers().unwrap();
 
+            let next_scene = scene.world().write_resource::<SceneChange>().0.take();
+            if let Some(next_scene) = next_scene {
+                // scenes might have changed the settings
+                if let Some(settings) = scene.world().try_fetch::<Settings>() {
+                    scene_ctx.session.settings = settings.clone();
+                }
+                match make_scene(next_scene, &scene_ctx) {
+                    Some(next) => {
+                        // drop the old scene first, it might hold on to audio
+                        drop(scene);
+                        scene = next;
+                        scene.setup();
+                    }
+                    None => {
+                        exit = true;
+                    }
+                }
+            }
+
             {
+                let world = scene.world();
                 let mut event_loop_msgs = world.fetch_mut::<Vec<EventLoopMsg>>();
                 let mut game_events = world.fetch_mut::<GameEvents>();
                 event_loop_msgs.clear();
@@ -66,6 +191,7 @@ pub fn game_thread(
             }
 
             for msg in event_loop_msg_rec.try_iter() {
+                let world = scene.world();
                 match msg {
                     EventLoopMsg::Quit => {
                         exit = true;
@@ -103,58 +229,22 @@ pub fn game_thread(
     game_thread_sender.send(GameThreadMsg::Quit).unwrap();
 }
 
-fn make_gameplay_world<'a>(
-    window_ctx: Rc<ContextWrapper<PossiblyCurrent, Window>>,
-    gpu_context: Rc<RefCell<GpuContext>>,
-) -> (World, Dispatcher<'a, 'a>) {
-    let mut world = World::new();
-
-    // components
-    world.register::<GamePos>();
-    world.register::<Circle>();
-    world.register::<Slider>();
-    world.register::<Lifetime>();
-    world.register::<CircleHitRating>();
-    world.register::<DespawnObject>();
-    world.register::<HitSound>();
-
-    // resources
-    world.insert(GameCursor {
-        window_x: 0.0,
-        window_y: 0.0,
-    });
-    world.insert(Time::default());
-    world.insert(Trail::default());
-    world.insert(TrailTimer::default());
-    world.insert(Hp::default());
-    world.insert(GameArea::default());
-    world.insert(Vec::<EventLoopMsg>::with_capacity(8));
-    world.insert(GameInputState::default());
-    world.insert(Combo::default());
-    world.insert(Score::default());
-    world.insert(GameEvents::default());
-
-    let game_dispatcher = DispatcherBuilder::new()
-        .with(InputSystem, "input-system", &[])
-        .with(TrailSystem, "trail-system", &["input-system"])
-        .with(ObjectSpawnerSystem::default(), "object-spawner-system", &[])
-        .with(HitSystem, "hit-system", &["object-spawner-system", "input-system"])
-        .with(CircleLifetimeSystem, "circle-lifetime-system", &["hit-system"])
-        .with(SliderLifetimeSystem, "slider-lifetime-system", &["hit-system"])
-        .with(LifetimeSystem, "lifetime-system" , &["circle-lifetime-system", "slider-lifetime-system"])
-        .with(ScoringSystem, "scoring-system", &["lifetime-system"])
-        .with_thread_local(AudioSystem::default())
-        .with_thread_local(RenderingSystem::new(window_ctx, gpu_context))
-        .build();
-
-    return (world, game_dispatcher);
+// `None` when the application should quit
+fn make_scene(next_scene: NextScene, ctx: &SceneContext) -> Option<Box<dyn Scene>> {
+    let scene: Box<dyn Scene> = match next_scene {
+        NextScene::MainMenu => Box::new(main_menu::make_main_menu_scene(ctx)),
+        NextScene::SongSelect => Box::new(song_select::make_song_select_scene(ctx)),
+        NextScene::Gameplay(game_config) => Box::new(gameplay::make_gameplay_scene(game_config, ctx)),
+        NextScene::Results(result, game_config) => Box::new(results::make_results_scene(result, game_config, ctx)),
+        NextScene::Settings => Box::new(settings_menu::make_settings_scene(ctx)),
+        NextScene::OffsetCalibration => Box::new(calibration::make_calibration_scene(ctx)),
+        NextScene::Quit => return None,
+    };
+    Some(scene)
 }
 
 fn update_time(world: &mut World, elapsed: Duration) {
+    let song_clock = world.read_resource::<SongClock>();
     let mut time = world.write_resource::<Time>();
-    let elapsed_in_secs = elapsed.as_secs_f64();
-    time.delta = elapsed;
-    time.delta_seconds = elapsed_in_secs;
-    time.secs_since_start += elapsed_in_secs;
-    time.now = Instant::now();
+    time.advance(elapsed, &song_clock);
 }
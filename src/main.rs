@@ -6,17 +6,41 @@ mod consts;
 mod rendering_system;
 mod utils;
 mod osu_parser;
+mod beatmap_loader;
+mod cli;
+mod mods;
+mod difficulty;
+mod settings;
+mod scoring;
+mod scenes;
+mod menu;
+mod autoplay;
+mod replay;
+mod time_stretch;
 
 use glutin::event_loop::{ControlFlow, EventLoop};
 use glutin::{WindowedContext, NotCurrent, ContextBuilder};
-use glutin::window::WindowBuilder;
+use glutin::window::{WindowBuilder, Fullscreen};
+use glutin::dpi::PhysicalSize;
 use glutin::event::{Event, WindowEvent, ElementState};
 
-use crate::game_thread::{game_thread, EventLoopMsg, GameThreadMsg};
+use crate::game_thread::{game_thread, EventLoopMsg, GameThreadMsg, GameConfig, NextScene, Session};
+use crate::beatmap_loader::BeatmapSet;
+use crate::cli::CliArgs;
+use crate::mods::Mod;
+use crate::difficulty::DifficultyAdjust;
+use crate::replay::Replay;
+use crate::scoring::ScoringMode;
+use crate::settings::Settings;
+use std::path::PathBuf;
 use std::sync::mpsc;
 use std::time::{Duration, Instant};
 use simple_logger::SimpleLogger;
 use log::LevelFilter;
+use structopt::StructOpt;
+
+// beatmap sets (folders and .osz archives) listed in song select
+const SONGS_FOLDER: &'static str = "songs";
 
 
 fn main() {
@@ -24,11 +48,94 @@ fn main() {
         .with_level(LevelFilter::Info)
         .init().unwrap();
 
+    let args = CliArgs::from_args();
+
+    let loaded_beatmap = match &args.beatmap {
+        Some(path) => {
+            let beatmap_set = BeatmapSet::open(path)
+                .unwrap_or_else(|err| exit_with_error(err));
+            if args.list_difficulties {
+                for difficulty in beatmap_set.difficulties() {
+                    println!("{}", difficulty.version);
+                }
+                return;
+            }
+            Some(beatmap_set.load(args.difficulty.as_deref())
+                .unwrap_or_else(|err| exit_with_error(err)))
+        }
+        None => None,
+    };
+
+    let mut settings = Settings::load();
+
+    if let Some(local_offset) = args.local_offset {
+        match &loaded_beatmap {
+            Some(loaded_beatmap) => {
+                settings.set_local_offset_ms(&loaded_beatmap.hash, local_offset);
+                settings.save();
+            }
+            None => log::warn!("--local-offset needs a beatmap, ignoring it"),
+        }
+    }
+
+    let replay = args.replay.as_deref().map(|path| {
+        let replay = Replay::load(path).unwrap_or_else(|err| exit_with_error(err));
+        match &loaded_beatmap {
+            Some(loaded_beatmap) if loaded_beatmap.hash == replay.beatmap_hash => replay,
+            Some(_) => exit_with_error(format!("The replay was recorded on another beatmap: {}", replay.beatmap_label)),
+            None => exit_with_error(format!("--replay needs the beatmap it was recorded on: {}", replay.beatmap_label)),
+        }
+    });
+
+    let mut mods = args.mods;
+    if args.autoplay {
+        mods.insert(Mod::Auto);
+        mods.check_compatible().unwrap_or_else(|err| exit_with_error(err));
+    }
+
+    let difficulty_adjust = DifficultyAdjust::new(args.cs, args.ar, args.od, args.hp, args.rate)
+        .unwrap_or_else(|err| exit_with_error(err));
+
+    let scoring_mode = if args.score_v2 { ScoringMode::V2 } else { ScoringMode::V1 };
+
+    // a beatmap given on the command line is played right away
+    let start_scene = if args.calibrate {
+        NextScene::OffsetCalibration
+    } else if let Some(loaded_beatmap) = loaded_beatmap {
+        NextScene::Gameplay(GameConfig {
+            audio_offset: settings.audio_offset(&loaded_beatmap.hash, args.offset),
+            beatmap: loaded_beatmap,
+            // replays play back with the settings they were recorded with
+            mods: replay.as_ref().map_or(mods, |replay| replay.mods),
+            difficulty_adjust: replay.as_ref().map_or(difficulty_adjust, |replay| replay.difficulty_adjust),
+            scoring_mode: replay.as_ref().map_or(scoring_mode, |replay| replay.scoring_mode),
+            replay,
+        })
+    } else {
+        NextScene::MainMenu
+    };
+
+    let session = Session {
+        settings,
+        mods,
+        difficulty_adjust,
+        scoring_mode,
+        offset_override_ms: args.offset,
+        songs_folder: PathBuf::from(SONGS_FOLDER),
+        extra_beatmap_set: args.beatmap.clone(),
+    };
+
     let event_loop = EventLoop::new();
 
     let window_ctx: WindowedContext<NotCurrent> = {
-        let window_builder = WindowBuilder::new()
+        let mut window_builder = WindowBuilder::new()
             .with_title("rusty-osu");
+        if let Some(size) = args.window_size {
+            window_builder = window_builder.with_inner_size(PhysicalSize::new(size.width, size.height));
+        }
+        if args.fullscreen {
+            window_builder = window_builder.with_fullscreen(Some(Fullscreen::Borderless(event_loop.primary_monitor())));
+        }
         ContextBuilder::new()
             .build_windowed(window_builder, &event_loop)
             .expect("Failed to build window context")
@@ -41,7 +148,7 @@ fn main() {
 
     std::thread::Builder::new()
         .name("GameThread".to_string())
-        .spawn(move || game_thread(window_ctx, ev_loop_receiver, game_thread_sender))
+        .spawn(move || game_thread(session, start_scene, window_ctx, ev_loop_receiver, game_thread_sender))
         .unwrap();
 
     let mut game_thread_exited = false;
@@ -114,4 +221,9 @@ fn main() {
             }
         }
     });
-}
\ No newline at end of file
+}
+
+fn exit_with_error(err: impl std::fmt::Display) -> ! {
+    log::error!("{}", err);
+    std::process::exit(1);
+}
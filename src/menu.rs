@@ -0,0 +1,230 @@
+use std::{cell::RefCell, rc::Rc};
+use glutin::event::VirtualKeyCode;
+use glutin::window::Window;
+use glutin::{ContextWrapper, PossiblyCurrent};
+use skia_safe::*;
+use skia_safe::font_style::{Weight, Width, Slant};
+use skia_safe::gpu::Context as GpuContext;
+use specs::{DispatcherBuilder, Read, System, World, WorldExt, Write};
+use crate::game_thread::{DispatcherScene, EventLoopMsg, SceneContext};
+use crate::resources::{GameEvents, Graphics};
+use crate::scenes::insert_scene_resources;
+
+// a simple vertical list of items, shared by all menu like scenes
+#[derive(Debug, Default)]
+pub struct Menu {
+    pub title: String,
+    // lines of text shown between the title and the items
+    pub info: Vec<String>,
+    pub items: Vec<String>,
+    pub selected_index: usize,
+}
+
+impl Menu {
+    pub fn new(title: &str, items: Vec<String>) -> Self {
+        Self {
+            title: title.to_string(),
+            info: Vec::new(),
+            items,
+            selected_index: 0,
+        }
+    }
+
+    pub fn select_next(&mut self) {
+        if !self.items.is_empty() {
+            self.selected_index = (self.selected_index + 1) % self.items.len();
+        }
+    }
+
+    pub fn select_previous(&mut self) {
+        if !self.items.is_empty() {
+            self.selected_index = (self.selected_index + self.items.len() - 1) % self.items.len();
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MenuAction {
+    Select(usize),
+    Back,
+    Left(usize),
+    Right(usize),
+}
+
+// actions taken in the menu this frame
+#[derive(Debug, Default)]
+pub struct MenuActions(pub Vec<MenuAction>);
+
+pub struct MenuInputSystem;
+
+impl<'a> System<'a> for MenuInputSystem {
+    type SystemData = (
+        Read<'a, Vec<EventLoopMsg>>,
+        Write<'a, Menu>,
+        Write<'a, MenuActions>,
+    );
+
+    fn run(&mut self, (event_loop_messages, mut menu, mut actions): Self::SystemData) {
+        actions.0.clear();
+
+        for event_loop_msg in event_loop_messages.iter() {
+            if let EventLoopMsg::KeyPressed(key) = event_loop_msg {
+                match key {
+                    VirtualKeyCode::Up => menu.select_previous(),
+                    VirtualKeyCode::Down => menu.select_next(),
+                    VirtualKeyCode::Left => actions.0.push(MenuAction::Left(menu.selected_index)),
+                    VirtualKeyCode::Right => actions.0.push(MenuAction::Right(menu.selected_index)),
+                    VirtualKeyCode::Return => actions.0.push(MenuAction::Select(menu.selected_index)),
+                    VirtualKeyCode::Escape => actions.0.push(MenuAction::Back),
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+pub struct MenuRenderingSystem {
+    graphics: Graphics,
+    gpu_context: Rc<RefCell<GpuContext>>,
+    window_ctx: Rc<ContextWrapper<PossiblyCurrent, Window>>,
+    title_font: Font,
+    font: Font,
+    font_paint: Paint,
+    selected_paint: Paint,
+}
+
+impl MenuRenderingSystem {
+    pub fn new(
+        window_ctx: Rc<ContextWrapper<PossiblyCurrent, Window>>,
+        gpu_context: Rc<RefCell<GpuContext>>,
+    ) -> Self {
+        let typeface = Typeface::new("Verdana", FontStyle::new(Weight::NORMAL, Width::NORMAL, Slant::Upright))
+            .unwrap();
+
+        let font_paint = {
+            let mut builder = Paint::default();
+            builder.set_anti_alias(true);
+            builder.set_color(Color::from_rgb(211, 211, 211));
+            builder.set_style(PaintStyle::Fill);
+            builder
+        };
+
+        let selected_paint = {
+            let mut builder = Paint::default();
+            builder.set_anti_alias(true);
+            builder.set_color(Color::from_rgb(252, 163, 17));
+            builder.set_alpha(90);
+            builder.set_style(PaintStyle::Fill);
+            builder
+        };
+
+        let graphics = Graphics::new(&window_ctx.clone(), &mut gpu_context.clone().borrow_mut());
+
+        Self {
+            graphics,
+            gpu_context,
+            window_ctx,
+            title_font: Font::new(typeface.clone(), 32.0),
+            font: Font::new(typeface, 18.0),
+            font_paint,
+            selected_paint,
+        }
+    }
+
+    fn draw_text(&self, canvas: &mut Canvas, text: &str, font: &Font, pos: Point) {
+        if let Some(blob) = TextBlob::from_str(text, font) {
+            canvas.draw_text_blob(blob, pos, &self.font_paint);
+        }
+    }
+
+    // draws the menu, `decorate` can draw scene specific things on top of it
+    pub fn draw(&mut self, events: &GameEvents, menu: &Menu, decorate: impl FnOnce(&mut Canvas, &Graphics)) {
+        events.on_resized(|_| {
+            self.graphics = Graphics::new(&self.window_ctx.clone(), &mut self.gpu_context.clone().borrow_mut());
+        });
+
+        let mut surface = self.graphics.surface.clone();
+        let canvas = surface.canvas();
+        canvas.clear(Color::from_rgb(24, 24, 24));
+
+        let left = 60.0;
+        let mut pos_y = 80.0;
+
+        self.draw_text(canvas, &menu.title, &self.title_font, Point::new(left, pos_y));
+        pos_y += 40.0;
+
+        for line in menu.info.iter() {
+            self.draw_text(canvas, line, &self.font, Point::new(left, pos_y));
+            pos_y += 26.0;
+        }
+        pos_y += 20.0;
+
+        // keep the selected item on screen for long lists
+        let item_height = 34.0;
+        let visible_items = (((self.graphics.height_f32 - pos_y) / item_height) as usize).max(1);
+        let first_visible = menu.selected_index
+            .saturating_sub(visible_items / 2)
+            .min(menu.items.len().saturating_sub(visible_items));
+
+        for (i, item) in menu.items.iter().enumerate().skip(first_visible).take(visible_items) {
+            if i == menu.selected_index {
+                canvas.draw_rect(
+                    Rect::new(left - 10.0, pos_y - 6.0, self.graphics.width_f32 - left, pos_y + item_height - 10.0),
+                    &self.selected_paint,
+                );
+            }
+            self.draw_text(canvas, item, &self.font, Point::new(left, pos_y + 16.0));
+            pos_y += item_height;
+        }
+
+        decorate(canvas, &self.graphics);
+
+        canvas.flush();
+    }
+}
+
+impl<'a> System<'a> for MenuRenderingSystem {
+    type SystemData = (
+        Read<'a, GameEvents>,
+        Read<'a, Menu>,
+    );
+
+    fn run(&mut self, (events, menu): Self::SystemData) {
+        self.draw(&events, &menu, |_, _| {});
+    }
+}
+
+// builds a scene that shows `menu` and lets `scene_system` react to the `MenuActions`
+pub fn make_menu_scene<S>(
+    world: World,
+    menu: Menu,
+    scene_system: S,
+    ctx: &SceneContext,
+) -> DispatcherScene where S: for<'c> System<'c> + Send + 'static {
+    let rendering_system = MenuRenderingSystem::new(ctx.window_ctx.clone(), ctx.gpu_context.clone());
+    make_menu_scene_with_rendering(world, menu, scene_system, rendering_system, ctx)
+}
+
+// same as `make_menu_scene`, for scenes drawing more than the menu itself
+pub fn make_menu_scene_with_rendering<S, R>(
+    mut world: World,
+    menu: Menu,
+    scene_system: S,
+    rendering_system: R,
+    ctx: &SceneContext,
+) -> DispatcherScene
+    where S: for<'c> System<'c> + Send + 'static,
+          R: for<'c> System<'c> + 'static {
+    insert_scene_resources(&mut world);
+    world.insert(menu);
+    world.insert(MenuActions::default());
+    world.insert(ctx.session.settings.clone());
+
+    let dispatcher = DispatcherBuilder::new()
+        .with(MenuInputSystem, "menu-input-system", &[])
+        .with(scene_system, "scene-system", &["menu-input-system"])
+        .with_thread_local(rendering_system)
+        .build();
+
+    DispatcherScene::new(world, dispatcher)
+}
@@ -0,0 +1,292 @@
+use std::fmt;
+use std::str::FromStr;
+use crate::components::CircleHitRating;
+use crate::scoring::ScoringMode;
+
+// gameplay mods, bit values match the ones osu! uses
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Mod {
+    Easy,
+    Hidden,
+    HardRock,
+    SuddenDeath,
+    DoubleTime,
+    Relax,
+    HalfTime,
+    Nightcore,
+    Flashlight,
+    Auto,
+    Autopilot,
+    Perfect,
+}
+
+pub const ALL_MODS: [Mod; 12] = [
+    Mod::Easy,
+    Mod::Hidden,
+    Mod::HardRock,
+    Mod::SuddenDeath,
+    Mod::DoubleTime,
+    Mod::Relax,
+    Mod::HalfTime,
+    Mod::Nightcore,
+    Mod::Flashlight,
+    Mod::Auto,
+    Mod::Autopilot,
+    Mod::Perfect,
+];
+
+// pairs of mods that can't be played together
+const INCOMPATIBLE_MODS: [(Mod, Mod); 7] = [
+    (Mod::Easy, Mod::HardRock),
+    (Mod::DoubleTime, Mod::HalfTime),
+    (Mod::Nightcore, Mod::HalfTime),
+    (Mod::SuddenDeath, Mod::Perfect),
+    (Mod::Auto, Mod::Relax),
+    (Mod::Auto, Mod::Autopilot),
+    (Mod::Relax, Mod::Autopilot),
+];
+
+impl Mod {
+    pub fn bit(&self) -> u32 {
+        match self {
+            Mod::Easy => 1 << 1,
+            Mod::Hidden => 1 << 3,
+            Mod::HardRock => 1 << 4,
+            Mod::SuddenDeath => 1 << 5,
+            Mod::DoubleTime => 1 << 6,
+            Mod::Relax => 1 << 7,
+            Mod::HalfTime => 1 << 8,
+            Mod::Nightcore => 1 << 9,
+            Mod::Flashlight => 1 << 10,
+            Mod::Auto => 1 << 11,
+            Mod::Autopilot => 1 << 13,
+            Mod::Perfect => 1 << 14,
+        }
+    }
+
+    pub fn acronym(&self) -> &'static str {
+        match self {
+            Mod::Easy => "EZ",
+            Mod::Hidden => "HD",
+            Mod::HardRock => "HR",
+            Mod::SuddenDeath => "SD",
+            Mod::DoubleTime => "DT",
+            Mod::Relax => "RX",
+            Mod::HalfTime => "HT",
+            Mod::Nightcore => "NC",
+            Mod::Flashlight => "FL",
+            Mod::Auto => "AT",
+            Mod::Autopilot => "AP",
+            Mod::Perfect => "PF",
+        }
+    }
+
+    // whether gameplay does anything with it yet
+    pub fn is_supported(&self) -> bool {
+        matches!(self, Mod::Easy | Mod::Hidden | Mod::HardRock | Mod::DoubleTime | Mod::HalfTime | Mod::Nightcore | Mod::Flashlight
+            | Mod::SuddenDeath | Mod::Perfect | Mod::Auto
+            | Mod::Relax | Mod::Autopilot)
+    }
+
+    // ScoreV2 rewards the harder mods more than ScoreV1 does
+    pub fn score_multiplier(&self, mode: ScoringMode) -> f64 {
+        match (self, mode) {
+            (Mod::Easy, _) => 0.5,
+            (Mod::Hidden, _) => 1.06,
+            (Mod::HardRock, ScoringMode::V1) => 1.06,
+            (Mod::HardRock, ScoringMode::V2) => 1.10,
+            (Mod::DoubleTime, ScoringMode::V1) | (Mod::Nightcore, ScoringMode::V1) => 1.12,
+            (Mod::DoubleTime, ScoringMode::V2) | (Mod::Nightcore, ScoringMode::V2) => 1.20,
+            (Mod::HalfTime, _) => 0.3,
+            (Mod::Flashlight, _) => 1.12,
+            _ => 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Mods {
+    bits: u32,
+}
+
+impl Mods {
+    pub fn contains(&self, m: Mod) -> bool {
+        self.bits & m.bit() == m.bit()
+    }
+
+    pub fn insert(&mut self, m: Mod) {
+        self.bits |= m.bit();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bits == 0
+    }
+
+    pub fn bits(&self) -> u32 {
+        self.bits
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=Mod> + '_ {
+        ALL_MODS.iter()
+            .cloned()
+            .filter(move |m| self.contains(*m))
+    }
+
+    pub fn check_compatible(&self) -> Result<(), ModsParseError> {
+        match INCOMPATIBLE_MODS.iter().find(|(a, b)| self.contains(*a) && self.contains(*b)) {
+            Some((a, b)) => Err(ModsParseError::Incompatible(*a, *b)),
+            None => Ok(()),
+        }
+    }
+
+    // song speed, gameplay time runs at the same rate
+    pub fn rate(&self) -> f64 {
+        if self.contains(Mod::DoubleTime) || self.contains(Mod::Nightcore) {
+            1.5
+        } else if self.contains(Mod::HalfTime) {
+            0.75
+        } else {
+            1.0
+        }
+    }
+
+    // sudden death fails on a miss, perfect on anything below a 300
+    pub fn fails_on(&self, rating: CircleHitRating) -> bool {
+        if self.contains(Mod::Perfect) {
+            rating < CircleHitRating::GREAT
+        } else if self.contains(Mod::SuddenDeath) {
+            rating == CircleHitRating::MISS
+        } else {
+            false
+        }
+    }
+
+    // plays with assistance don't count
+    pub fn is_ranked(&self) -> bool {
+        !(self.contains(Mod::Auto) || self.contains(Mod::Relax) || self.contains(Mod::Autopilot))
+    }
+
+    // all mod multipliers combined
+    pub fn score_multiplier(&self, mode: ScoringMode) -> f64 {
+        self.iter()
+            // nightcore is double time with a beat, don't count it twice
+            .filter(|m| !(*m == Mod::Nightcore && self.contains(Mod::DoubleTime)))
+            .map(|m| m.score_multiplier(mode))
+            .product()
+    }
+}
+
+#[derive(Debug)]
+pub enum ModsParseError {
+    Unknown(String),
+    Incompatible(Mod, Mod),
+}
+
+impl fmt::Display for ModsParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModsParseError::Unknown(acronym) => write!(f, "Unknown mod {}", acronym),
+            ModsParseError::Incompatible(a, b) => {
+                write!(f, "{} and {} can't be played together", a.acronym(), b.acronym())
+            }
+        }
+    }
+}
+
+impl FromStr for Mods {
+    type Err = ModsParseError;
+
+    // accepts "HDHR", "HD,HR", "+hd hr" and so on
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let letters = s.chars()
+            .filter(char::is_ascii_alphanumeric)
+            .map(|c| c.to_ascii_uppercase())
+            .collect::<Vec<_>>();
+
+        let mut mods = Mods::default();
+        for chunk in letters.chunks(2) {
+            let acronym = chunk.iter().collect::<String>();
+            let m = ALL_MODS.iter()
+                .find(|m| m.acronym() == acronym)
+                .ok_or_else(|| ModsParseError::Unknown(acronym.clone()))?;
+            mods.insert(*m);
+        }
+        mods.check_compatible()?;
+        Ok(mods)
+    }
+}
+
+impl fmt::Display for Mods {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "NM");
+        }
+        for m in self.iter() {
+            write!(f, "{}", m.acronym())?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn mods_parse_test() {
+    let mods = Mods::from_str("+hd,HR").unwrap();
+    assert!(mods.contains(Mod::Hidden));
+    assert!(mods.contains(Mod::HardRock));
+    assert!(!mods.contains(Mod::Easy));
+    assert_eq!(mods.to_string(), "HDHR");
+    assert_eq!(Mods::from_str("").unwrap().to_string(), "NM");
+    assert!(Mods::from_str("XX").is_err());
+}
+
+#[test]
+fn mods_incompatible_test() {
+    let err = Mods::from_str("HREZ").unwrap_err();
+    assert!(matches!(err, ModsParseError::Incompatible(Mod::Easy, Mod::HardRock)));
+    assert_eq!(err.to_string(), "EZ and HR can't be played together");
+    assert!(Mods::from_str("SDPF").is_err());
+    assert!(Mods::from_str("ATRX").is_err());
+    assert!(Mods::from_str("RXAP").is_err());
+    assert!(Mods::from_str("DTHT").is_err());
+    assert!(Mods::from_str("NCHT").is_err());
+    assert!(Mods::from_str("HDHRSD").is_ok());
+}
+
+#[test]
+fn mods_rate_test() {
+    assert_eq!(Mods::from_str("").unwrap().rate(), 1.0);
+    assert_eq!(Mods::from_str("HDDT").unwrap().rate(), 1.5);
+    assert_eq!(Mods::from_str("NC").unwrap().rate(), 1.5);
+    assert_eq!(Mods::from_str("HT").unwrap().rate(), 0.75);
+}
+
+#[test]
+fn mods_ranked_test() {
+    assert!(Mods::from_str("HDHR").unwrap().is_ranked());
+    assert!(!Mods::from_str("RX").unwrap().is_ranked());
+    assert!(!Mods::from_str("HDAP").unwrap().is_ranked());
+}
+
+#[test]
+fn mods_fails_on_test() {
+    let sudden_death = Mods::from_str("SD").unwrap();
+    assert!(sudden_death.fails_on(CircleHitRating::MISS));
+    assert!(!sudden_death.fails_on(CircleHitRating::MEH));
+
+    let perfect = Mods::from_str("PF").unwrap();
+    assert!(perfect.fails_on(CircleHitRating::GOOD));
+    assert!(!perfect.fails_on(CircleHitRating::GREAT));
+
+    assert!(!Mods::default().fails_on(CircleHitRating::MISS));
+}
+
+#[test]
+fn mods_score_multiplier_test() {
+    assert_eq!(Mods::from_str("").unwrap().score_multiplier(ScoringMode::V1), 1.0);
+    assert!((Mods::from_str("HDHR").unwrap().score_multiplier(ScoringMode::V1) - 1.1236).abs() < 1e-9);
+    assert!((Mods::from_str("DTNC").unwrap().score_multiplier(ScoringMode::V1) - 1.12).abs() < 1e-9);
+    assert_eq!(Mods::from_str("EZ").unwrap().score_multiplier(ScoringMode::V1), 0.5);
+    assert!((Mods::from_str("HDHR").unwrap().score_multiplier(ScoringMode::V2) - 1.166).abs() < 1e-9);
+    assert_eq!(Mods::from_str("DTNC").unwrap().score_multiplier(ScoringMode::V2), 1.2);
+    assert_eq!(Mods::from_str("HT").unwrap().score_multiplier(ScoringMode::V2), 0.3);
+}
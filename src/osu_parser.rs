@@ -5,16 +5,57 @@ use std::time::Duration;
 
 type OsuDecimal = f64;
 
+const PLAYFIELD_HEIGHT: f32 = 384.0;
+
 #[derive(Debug, Clone)]
 pub struct OsuBeatMap {
+    pub title: String,
+    pub artist: String,
+    pub creator: String,
+    pub version: String,
     pub audio_file_name: String,
+    pub background_file_name: Option<String>,
+    pub sample_set: String,
     pub audio_lead_in: OsuDecimal,
     pub stack_leniency: OsuDecimal,
     pub slider_multiplier: OsuDecimal,
+    pub slider_tick_rate: OsuDecimal,
+    pub hp_drain_rate: OsuDecimal,
+    pub circle_size: OsuDecimal,
+    pub overall_difficulty: OsuDecimal,
+    pub approach_rate: OsuDecimal,
     pub timing_points: Vec<TimingPoint>,
     pub hit_objects: Vec<OsuBeatMapHitObject>,
 }
 
+impl OsuBeatMap {
+    // first object start and last object end, sliders count by their start
+    pub fn object_time_range_in_secs(&self) -> (f64, f64) {
+        let first = match self.hit_objects.first() {
+            Some(first) => first,
+            None => return (0.0, 0.0),
+        };
+        let last = self.hit_objects.last().unwrap();
+        let last_end = match &last.object_params {
+            Some(OsuBeatMapHitObjectParams::Spinner(spinner)) => spinner.end_time_in_secs,
+            _ => last.time_offset_in_secs,
+        };
+        (first.time_offset_in_secs, last_end)
+    }
+
+    // hard rock plays the map upside down
+    pub fn flip_vertically(&mut self) {
+        for hit_object in self.hit_objects.iter_mut() {
+            hit_object.y = PLAYFIELD_HEIGHT - hit_object.y;
+            if let Some(OsuBeatMapHitObjectParams::Slider(slider)) = &mut hit_object.object_params {
+                for point in slider.curve_points.iter_mut() {
+                    point.1 = PLAYFIELD_HEIGHT - point.1;
+                }
+            }
+        }
+    }
+}
+
 #[derive(Default, Copy, Clone, Debug)]
 pub struct OsuBeatMapParseConfig {
     pub pre_add_audio_lead_in: bool,
@@ -31,6 +72,7 @@ pub fn parse_osu_file(
     let mut strings = HashMap::new();
     let mut decimals = HashMap::new();
     let mut timing_points = Vec::new();
+    let mut background_file_name = None;
     let mut hit_objects = Vec::with_capacity(1024);
 
     while let Some(line) = it.next() {
@@ -48,13 +90,39 @@ pub fn parse_osu_file(
                         "AudioFilename" => on_string_key_value(&mut strings, key, value_raw),
                         "AudioLeadIn" => on_decimal_key_value(&mut decimals, key, value_raw),
                         "StackLeniency" => on_decimal_key_value(&mut decimals, key, value_raw),
+                        "SampleSet" => on_string_key_value(&mut strings, key, value_raw),
+                        _ => return
+                    };
+                }),
+                "Metadata" => parse_section(&mut it, |line| {
+                    let (key, value_raw) = key_value_line(line);
+                    match key.as_str() {
+                        "Title" | "Artist" | "Creator" | "Version" => on_string_key_value(&mut strings, key, value_raw),
                         _ => return
                     };
                 }),
+                "Events" => parse_section(&mut it, |line| {
+                    if line.starts_with("//") || background_file_name.is_some() {
+                        return;
+                    }
+                    // background events look like `0,0,"bg.jpg",0,0`
+                    let values = line.split(",")
+                        .map(str::trim)
+                        .collect::<Vec<_>>();
+                    let is_background = values[0] == "0" || values[0] == "Background";
+                    if is_background && values.len() > 2 {
+                        background_file_name = Some(values[2].trim_matches('"').to_string());
+                    }
+                }),
                 "Difficulty" => parse_section(&mut it, |line| {
                     let (key, value_raw) = key_value_line(line);
                     match key.as_str() {
                         "SliderMultiplier" => on_decimal_key_value(&mut decimals, key, value_raw),
+                        "SliderTickRate" => on_decimal_key_value(&mut decimals, key, value_raw),
+                        "HPDrainRate" => on_decimal_key_value(&mut decimals, key, value_raw),
+                        "CircleSize" => on_decimal_key_value(&mut decimals, key, value_raw),
+                        "OverallDifficulty" => on_decimal_key_value(&mut decimals, key, value_raw),
+                        "ApproachRate" => on_decimal_key_value(&mut decimals, key, value_raw),
                         _ => return
                     };
                 }),
@@ -65,10 +133,12 @@ pub fn parse_osu_file(
                         .collect::<Vec<OsuDecimal>>();
                     let time_offset = values[0] as u64;
                     let beat_length = values[1];
+                    let meter = values[2] as u32;
                     let inherited = values[6] == 0.0;
                     timing_points.push(TimingPoint {
                         time_offset_in_millis: time_offset,
                         beat_length,
+                        meter: if meter == 0 { 4 } else { meter },
                         inherited,
                     });
                 }),
@@ -136,6 +206,12 @@ pub fn parse_osu_file(
                                 length,
                             };
                             Some(OsuBeatMapHitObjectParams::Slider(params))
+                        } else if is_nth_bit_set(hit_obj_type, 3) {
+                            let end_time_in_millis = u64::from_str(rows[5]).unwrap();
+                            Some(OsuBeatMapHitObjectParams::Spinner(OsuBeatMapHitObjectSpinnerParams {
+                                end_time_in_millis,
+                                end_time_in_secs: Duration::from_millis(end_time_in_millis).as_secs_f64(),
+                            }))
                         } else {
                             None
                         }
@@ -164,17 +240,44 @@ pub fn parse_osu_file(
         for hit_object in hit_objects.iter_mut() {
             hit_object.time_offset_in_secs += audio_lead_in_in_secs;
             hit_object.time_offset_in_millis += audio_lead_in_in_ms;
+            if let Some(OsuBeatMapHitObjectParams::Spinner(spinner)) = &mut hit_object.object_params {
+                spinner.end_time_in_secs += audio_lead_in_in_secs;
+                spinner.end_time_in_millis += audio_lead_in_in_ms;
+            }
         }
         for timing_point in timing_points.iter_mut() {
             timing_point.time_offset_in_millis += audio_lead_in_in_ms;
         }
     };
 
+    let string_or_empty = |key: &str| strings.get(key)
+        .cloned()
+        .unwrap_or_default();
+    // defaults as documented in the .osu file format
+    let decimal_or = |key: &str, default: OsuDecimal| decimals.get(key)
+        .cloned()
+        .unwrap_or(default);
+    let overall_difficulty = decimal_or("OverallDifficulty", 5.0);
+
     return OsuBeatMap {
+        title: string_or_empty("Title"),
+        artist: string_or_empty("Artist"),
+        creator: string_or_empty("Creator"),
+        version: string_or_empty("Version"),
         audio_file_name: strings["AudioFilename"].clone(),
+        background_file_name,
+        sample_set: strings.get("SampleSet")
+            .cloned()
+            .unwrap_or_else(|| "Normal".to_string()),
         audio_lead_in: decimals["AudioLeadIn"].clone(),
         stack_leniency: decimals["StackLeniency"].clone(),
         slider_multiplier: decimals["SliderMultiplier"].clone(),
+        slider_tick_rate: decimal_or("SliderTickRate", 1.0),
+        hp_drain_rate: decimal_or("HPDrainRate", 5.0),
+        circle_size: decimal_or("CircleSize", 5.0),
+        overall_difficulty,
+        // old maps don't have an approach rate, it used to be the overall difficulty
+        approach_rate: decimal_or("ApproachRate", overall_difficulty),
         timing_points,
         hit_objects,
     };
@@ -184,6 +287,8 @@ pub fn parse_osu_file(
 pub struct TimingPoint {
     pub time_offset_in_millis: u64,
     pub beat_length: f64,
+    // beats per measure
+    pub meter: u32,
     pub inherited: bool,
 }
 
@@ -201,6 +306,13 @@ pub struct OsuBeatMapHitObject {
 pub enum OsuBeatMapHitObjectParams {
     HitCircle,
     Slider(OsuBeatMapHitObjectSliderParams),
+    Spinner(OsuBeatMapHitObjectSpinnerParams),
+}
+
+#[derive(Debug, Clone)]
+pub struct OsuBeatMapHitObjectSpinnerParams {
+    pub end_time_in_millis: u64,
+    pub end_time_in_secs: f64,
 }
 
 #[derive(Debug, Clone)]
@@ -257,7 +369,8 @@ fn parse_section<F: FnMut(&str)>(
 }
 
 fn key_value_line(line: &str) -> (String, String) {
-    let key_value_vector = line.split(":")
+    // values (eg. titles) might contain a colon too
+    let key_value_vector = line.splitn(2, ":")
         .map(str::trim)
         .map(str::to_string)
         .collect::<Vec<_>>();
@@ -1,12 +1,15 @@
 use std::{cell::RefCell, rc::Rc};
+use std::path::Path;
 use glutin::window::Window;
 use skia_safe::*;
-use specs::{Join, Read, ReadStorage, System, WriteExpect};
+use specs::{Join, Read, ReadExpect, ReadStorage, System, WriteExpect};
 use crate::{consts::*, resources::GameEvents};
-use crate::components::{Circle, GamePos, Lifetime, Slider, SliderState};
-use crate::resources::{Graphics, Time, GameCursor, Trail, Hp, GameArea, GameInputState, Combo, Score, GameInputKeyBinding};
+use crate::components::{Circle, CircleHitRating, GamePos, Judgement, JudgementPopup, Lifetime, Shake, Slider, SliderState, Spinner};
+use crate::resources::{Graphics, Time, GameCursor, Trail, Hp, GameArea, GameInputState, Combo, Score, GameInputKeyBinding, PauseMenu, PAUSE_MENU_ITEMS, MapProgress, PlayStats, HitErrorMeter, ReplayPlayback};
 use splines::{Spline, Interpolation, Key};
 use crate::utils::{min_f32};
+use crate::difficulty::PlayDifficulty;
+use crate::mods::{Mod, Mods};
 use skia_safe::font_style::{Weight, Width, Slant};
 use skia_safe::gpu::Context as GpuContext;
 
@@ -18,10 +21,30 @@ pub struct RenderingSystem {
     paints: Paints,
     splines: Splines,
     fonts: Fonts,
+    background: Option<Image>,
+    difficulty: PlayDifficulty,
 }
 
 struct Fonts {
     default: Font,
+    hud_large: Font,
+    hud_small: Font,
+    // drawn inside the game area, so not scaled here
+    judgement: Font,
+}
+
+impl Fonts {
+    // hud fonts follow the window height, 720p being 1.0
+    fn new(hud_scale: f32) -> Self {
+        let typeface = Typeface::new("Verdana", FontStyle::new(Weight::NORMAL, Width::NORMAL, Slant::Upright))
+            .unwrap();
+        Self {
+            default: Font::new(typeface.clone(), 18.0),
+            hud_large: Font::new(typeface.clone(), 36.0 * hud_scale),
+            hud_small: Font::new(typeface.clone(), 20.0 * hud_scale),
+            judgement: Font::new(typeface, 28.0),
+        }
+    }
 }
 
 struct Shapes {
@@ -38,6 +61,14 @@ struct Paints {
     font_default: Paint,
     slider: Paint,
     slider_drag: Paint,
+    background_dim: Paint,
+    overlay: Paint,
+    hud_progress: Paint,
+    replay_cursor: Paint,
+    judgement_great: Paint,
+    judgement_good: Paint,
+    judgement_meh: Paint,
+    judgement_miss: Paint,
 }
 
 struct Splines {
@@ -45,6 +76,16 @@ struct Splines {
     circle_life_spline: Spline<f64, f32>,
     key_cap_light_on_spline: Spline<f32, f32>,
     slider_hold_circle: Spline<f64, f32>,
+    hit_error_tick_fade: Spline<f64, f32>,
+    judgement_fade: Spline<f64, f32>,
+    judgement_scale: Spline<f64, f32>,
+    // misses and slider breaks drop down instead of popping
+    judgement_drop: Spline<f64, f32>,
+    shake_offset: Spline<f64, f32>,
+    // hidden fades objects in and out well before they have to be hit
+    hidden_fade: Spline<f64, f32>,
+    // from the head fading out (0.0) to the end of the slider (1.0)
+    hidden_slider_body_fade: Spline<f64, f32>,
 }
 
 impl<'a> System<'a> for RenderingSystem {
@@ -57,11 +98,20 @@ impl<'a> System<'a> for RenderingSystem {
         Read<'a, Combo>,
         Read<'a, GameInputState>,
         Read<'a, GameEvents>,
+        Read<'a, PauseMenu>,
+        Read<'a, MapProgress>,
+        Read<'a, PlayStats>,
+        Read<'a, HitErrorMeter>,
+        Read<'a, Mods>,
         WriteExpect<'a, GameArea>,
         ReadStorage<'a, GamePos>,
         ReadStorage<'a, Circle>,
         ReadStorage<'a, Slider>,
+        ReadStorage<'a, Spinner>,
         ReadStorage<'a, Lifetime>,
+        ReadStorage<'a, JudgementPopup>,
+        ReadStorage<'a, Shake>,
+        Option<ReadExpect<'a, ReplayPlayback>>,
     );
 
     fn run(&mut self, (
@@ -73,11 +123,20 @@ impl<'a> System<'a> for RenderingSystem {
         combo,
         input_state,
         events,
+        pause_menu,
+        map_progress,
+        stats,
+        hit_error_meter,
+        mods,
         mut game_area,
         positions,
         circles,
         sliders,
+        spinners,
         lifetimes,
+        popups,
+        shakes,
+        playback,
     ): Self::SystemData) {
         events.on_resized(|_| {
             self.on_resize();
@@ -86,6 +145,24 @@ impl<'a> System<'a> for RenderingSystem {
         let mut surface = self.graphics.surface.clone();
         surface.canvas().clear(Color::from_rgb(24, 24, 24));
 
+        if let Some(background) = &self.background {
+            // scale the background so it covers the whole window
+            let scale = {
+                let width_scale = self.graphics.width_f32 / background.width() as f32;
+                let height_scale = self.graphics.height_f32 / background.height() as f32;
+                if width_scale > height_scale { width_scale } else { height_scale }
+            };
+            let canvas = surface.canvas();
+            canvas.save();
+            canvas.translate((
+                (self.graphics.width_f32 - background.width() as f32 * scale) / 2.0,
+                (self.graphics.height_f32 - background.height() as f32 * scale) / 2.0,
+            ));
+            canvas.scale((scale, scale));
+            canvas.draw_image(background, Point::new(0.0, 0.0), Some(&self.paints.background_dim));
+            canvas.restore();
+        }
+
         { // do all the game area drawing here
             let mut ctx = RenderingCtx {
                 canvas: surface.canvas(),
@@ -93,6 +170,8 @@ impl<'a> System<'a> for RenderingSystem {
                 paints: &self.paints,
                 fonts: &self.fonts,
                 shapes: &self.shapes,
+                difficulty: &self.difficulty,
+                mods: &mods,
             };
 
             const PADDING: f32 = 100.0;
@@ -121,9 +200,9 @@ impl<'a> System<'a> for RenderingSystem {
             );
 
 
-            for (_, pos, lifetime) in (&circles, &positions, &lifetimes).join() {
+            for (_, pos, lifetime, shake) in (&circles, &positions, &lifetimes, shakes.maybe()).join() {
                 ctx.draw_circle(
-                    pos, lifetime,
+                    pos, lifetime, shake, &time,
                 );
             }
 
@@ -136,12 +215,46 @@ impl<'a> System<'a> for RenderingSystem {
                 );
             }
 
+            for (spinner, lifetime) in (&spinners, &lifetimes).join() {
+                ctx.draw_spinner(spinner, lifetime, &time);
+            }
+
+            for (popup, pos) in (&popups, &positions).join() {
+                ctx.draw_judgement_popup(popup, pos, &time);
+            }
+
             ctx.draw_user_hit(&input_state, &time);
 
             ctx.canvas.restore();
 
-            ctx.draw_text(format!("Combo: {}", combo.value), Point::new(12.0, self.graphics.height_f32 - 50.0));
-            ctx.draw_text(format!("Score: {}", score.value), Point::new(12.0, self.graphics.height_f32 - 25.0));
+            if mods.contains(Mod::Flashlight) {
+                let sliding = (&sliders).join()
+                    .any(|slider| matches!(slider.state, SliderState::DRAGGING(_)));
+                ctx.draw_flashlight(
+                    Point::new(cursor.window_x, cursor.window_y),
+                    flashlight_radius(combo.value) * scale.0,
+                    sliding,
+                    self.graphics.width_f32,
+                    self.graphics.height_f32,
+                );
+            }
+
+            ctx.draw_hit_error_meter(
+                &hit_error_meter,
+                &time,
+                Point::new(self.graphics.width_f32 / 2.0, translation.1 + 480.0 * scale.1 + 25.0),
+                self.graphics.height_f32 / 720.0,
+            );
+
+            ctx.draw_hud(
+                self.graphics.width_f32,
+                self.graphics.height_f32,
+                &score,
+                &combo,
+                &stats,
+                &mods,
+                map_progress.fraction(&time) as f32,
+            );
         }
 
         for (i, cords) in trail.iter().skip(1).enumerate() {
@@ -168,6 +281,39 @@ impl<'a> System<'a> for RenderingSystem {
             &self.paints.cursor,
         );
 
+        if let Some(playback) = &playback {
+            let mut ctx = RenderingCtx {
+                canvas: surface.canvas(),
+                splines: &self.splines,
+                paints: &self.paints,
+                fonts: &self.fonts,
+                shapes: &self.shapes,
+                difficulty: &self.difficulty,
+                mods: &mods,
+            };
+            ctx.draw_replay_overlay(
+                playback,
+                &input_state,
+                Point::new(cursor.window_x, cursor.window_y),
+                map_progress.fraction(&time) as f32,
+                self.graphics.width_f32,
+                self.graphics.height_f32,
+            );
+        }
+
+        if pause_menu.active {
+            let mut ctx = RenderingCtx {
+                canvas: surface.canvas(),
+                splines: &self.splines,
+                paints: &self.paints,
+                fonts: &self.fonts,
+                shapes: &self.shapes,
+                difficulty: &self.difficulty,
+                mods: &mods,
+            };
+            ctx.draw_pause_overlay(&pause_menu, self.graphics.width_f32, self.graphics.height_f32);
+        }
+
         surface.canvas().flush();
     }
 }
@@ -181,6 +327,8 @@ struct RenderingCtx<'a> {
     paints: &'a Paints,
     fonts: &'a Fonts,
     shapes: &'a Shapes,
+    difficulty: &'a PlayDifficulty,
+    mods: &'a Mods,
 }
 
 impl<'a> RenderingCtx<'a> {
@@ -234,6 +382,246 @@ impl<'a> RenderingCtx<'a> {
         self.canvas.draw_rect(pos, &self.paints.key_cap_off);
     }
 
+    fn draw_pause_overlay(
+        &mut self,
+        pause_menu: &PauseMenu,
+        width: f32,
+        height: f32,
+    ) {
+        self.canvas.draw_rect(Rect::new(0.0, 0.0, width, height), &self.paints.overlay);
+
+        let item_height = 40.0;
+        let top = (height - item_height * PAUSE_MENU_ITEMS.len() as f32) / 2.0;
+        for (i, item) in PAUSE_MENU_ITEMS.iter().enumerate() {
+            let pos_y = top + i as f32 * item_height;
+            if i == pause_menu.selected_index {
+                self.canvas.draw_rect(
+                    Rect::new(width / 2.0 - 100.0, pos_y, width / 2.0 + 100.0, pos_y + item_height - 8.0),
+                    &self.paints.key_cap_off,
+                );
+            }
+            self.draw_text(item.label().to_string(), Point::new(width / 2.0 - 80.0, pos_y + 24.0));
+        }
+    }
+
+    fn draw_hud(
+        &mut self,
+        width: f32,
+        height: f32,
+        score: &Score,
+        combo: &Combo,
+        stats: &PlayStats,
+        mods: &Mods,
+        progress: f32,
+    ) {
+        let (fonts, paints) = (self.fonts, self.paints);
+        let scale = height / 720.0;
+        let margin = 16.0 * scale;
+        let right = width - margin;
+
+        // score with accuracy and song progress below it, top right
+        let score_y = 10.0 + margin + 36.0 * scale;
+        self.draw_text_right(format!("{:08}", score.value()), &fonts.hud_large, &paints.font_default, right, score_y);
+
+        let accuracy_y = score_y + 30.0 * scale;
+        let accuracy_width = self.draw_text_right(
+            format!("{:.2}%", stats.accuracy() * 100.0),
+            &fonts.hud_small,
+            &paints.font_default,
+            right,
+            accuracy_y,
+        );
+
+        let pie_radius = 9.0 * scale;
+        let pie_center = Point::new(right - accuracy_width - 12.0 * scale - pie_radius, accuracy_y - 7.0 * scale);
+        let pie_rect = Rect::new(
+            pie_center.x - pie_radius,
+            pie_center.y - pie_radius,
+            pie_center.x + pie_radius,
+            pie_center.y + pie_radius,
+        );
+        self.canvas.draw_circle(pie_center, pie_radius, &paints.follow_circle);
+        self.canvas.draw_arc(pie_rect, -90.0, 360.0 * progress, true, &paints.hud_progress);
+
+        if !mods.is_empty() {
+            self.draw_text_right(mods.to_string(), &fonts.hud_small, &paints.font_default, right, accuracy_y + 28.0 * scale);
+        }
+
+        // judgement counters on the right side
+        let counters = [
+            ("300", stats.great, &paints.judgement_great),
+            ("100", stats.good, &paints.judgement_good),
+            ("50", stats.meh, &paints.judgement_meh),
+            ("Miss", stats.miss, &paints.judgement_miss),
+        ];
+        for (i, (label, count, paint)) in counters.iter().enumerate() {
+            let pos_y = height / 2.0 + i as f32 * 26.0 * scale;
+            self.draw_text_right(format!("{} {}", label, count), &fonts.hud_small, paint, right, pos_y);
+        }
+
+        self.draw_text_with(
+            format!("{}x", combo.value),
+            &fonts.hud_large,
+            &paints.font_default,
+            Point::new(margin, height - margin),
+        );
+
+        let unstable_rate = match stats.unstable_rate() {
+            Some(unstable_rate) => format!("{:.2} UR", unstable_rate),
+            None => "- UR".to_string(),
+        };
+        let (unstable_rate_width, _) = fonts.hud_small.measure_str(&unstable_rate, None);
+        self.draw_text_with(
+            unstable_rate,
+            &fonts.hud_small,
+            &paints.font_default,
+            Point::new((width - unstable_rate_width) / 2.0, height - margin),
+        );
+    }
+
+    // the replay's cursor with its keys, and a seek bar along the bottom
+    fn draw_replay_overlay(
+        &mut self,
+        playback: &ReplayPlayback,
+        input_state: &GameInputState,
+        cursor: Point,
+        progress: f32,
+        width: f32,
+        height: f32,
+    ) {
+        let (fonts, paints) = (self.fonts, self.paints);
+        let scale = height / 720.0;
+
+        self.canvas.draw_circle(cursor, 20.0, &paints.replay_cursor);
+        if input_state.is_key_hold(&GameInputKeyBinding::Hit1) || input_state.is_key_hold(&GameInputKeyBinding::Hit2) {
+            self.canvas.draw_circle(cursor, 6.0, &paints.hud_progress);
+        }
+
+        let margin = 40.0 * scale;
+        let bar_y = height - 12.0 * scale;
+        let bar_height = 4.0 * scale;
+        self.canvas.draw_rect(
+            Rect::new(margin, bar_y, width - margin, bar_y + bar_height),
+            &paints.follow_circle,
+        );
+        self.canvas.draw_rect(
+            Rect::new(margin, bar_y, margin + (width - 2.0 * margin) * progress.max(0.0).min(1.0), bar_y + bar_height),
+            &paints.hud_progress,
+        );
+
+        let label = if playback.is_seeking() {
+            "Replay, seeking...".to_string()
+        } else {
+            format!("Replay {:.2}x  Left/Right seek  Up/Down speed", playback.speed)
+        };
+        self.draw_text_right(label, &fonts.hud_small, &paints.font_default, width - margin, bar_y - 8.0 * scale);
+    }
+
+    // everything but a soft edged circle around the cursor goes dark
+    fn draw_flashlight(
+        &mut self,
+        center: Point,
+        radius: f32,
+        sliding: bool,
+        width: f32,
+        height: f32,
+    ) {
+        // the visible part gets dimmed while a slider is held
+        let inner = if sliding { Color::from_argb(190, 0, 0, 0) } else { Color::TRANSPARENT };
+        let colors = [inner, inner, Color::BLACK];
+        let positions = [0.0, 0.75, 1.0];
+        let shader = gradient_shader::radial(
+            center,
+            radius,
+            &colors[..],
+            &positions[..],
+            TileMode::Clamp,
+            None,
+            None,
+        );
+
+        let mut paint = Paint::default();
+        paint.set_anti_alias(true);
+        paint.set_shader(shader);
+        self.canvas.draw_rect(Rect::new(0.0, 0.0, width, height), &paint);
+    }
+
+    // early hits on the left, late ones on the right
+    fn draw_hit_error_meter(
+        &mut self,
+        meter: &HitErrorMeter,
+        time: &Time,
+        center: Point,
+        scale: f32,
+    ) {
+        let half_width = 120.0 * scale;
+        let window_height = 6.0 * scale;
+        let hit_windows = self.difficulty.hit_windows;
+        let x_of = |error: f64| center.x + (error / hit_windows.meh) as f32 * half_width;
+
+        let windows = [
+            (hit_windows.meh, &self.paints.judgement_meh),
+            (hit_windows.good, &self.paints.judgement_good),
+            (hit_windows.great, &self.paints.judgement_great),
+        ];
+        for (window, paint) in windows.iter() {
+            let mut paint = (*paint).clone();
+            paint.set_alpha(140);
+            self.canvas.draw_rect(
+                Rect::new(x_of(-window), center.y - window_height / 2.0, x_of(*window), center.y + window_height / 2.0),
+                &paint,
+            );
+        }
+
+        let tick_height = 16.0 * scale;
+        for tick in meter.iter() {
+            let alpha = self.splines.hit_error_tick_fade.clamped_sample(time.secs_since_start - tick.at)
+                .unwrap();
+            let mut paint = match hit_windows.rating(tick.error) {
+                Some(CircleHitRating::GREAT) => self.paints.judgement_great.clone(),
+                Some(CircleHitRating::GOOD) => self.paints.judgement_good.clone(),
+                _ => self.paints.judgement_meh.clone(),
+            };
+            paint.set_alpha_f(alpha);
+            let x = x_of(tick.error);
+            self.canvas.draw_rect(
+                Rect::new(x - scale, center.y - tick_height / 2.0, x + scale, center.y + tick_height / 2.0),
+                &paint,
+            );
+        }
+
+        self.canvas.draw_rect(
+            Rect::new(center.x - scale, center.y - tick_height * 0.75, center.x + scale, center.y + tick_height * 0.75),
+            &self.paints.font_default,
+        );
+    }
+
+    // draws `text` ending at `right`, returns its width
+    fn draw_text_right(
+        &mut self,
+        text: String,
+        font: &Font,
+        paint: &Paint,
+        right: f32,
+        pos_y: f32,
+    ) -> f32 {
+        let (text_width, _) = font.measure_str(&text, None);
+        self.draw_text_with(text, font, paint, Point::new(right - text_width, pos_y));
+        text_width
+    }
+
+    fn draw_text_with(
+        &mut self,
+        text: String,
+        font: &Font,
+        paint: &Paint,
+        pos: Point,
+    ) {
+        if let Some(blob) = TextBlob::from_str(text.as_str(), font) {
+            self.canvas.draw_text_blob(blob, pos, paint);
+        }
+    }
+
     fn draw_text(
         &mut self,
         text: String,
@@ -254,14 +642,28 @@ impl<'a> RenderingCtx<'a> {
         lifetime: &Lifetime,
         time: &Time,
     ) {
-        self.canvas.draw_path(&slider.skia_path, &self.paints.slider);
-        let lifetime = if lifetime.is_dead() { Lifetime::zero() } else { lifetime };
-        self.draw_circle(pos, lifetime);
+        if self.mods.contains(Mod::Hidden) {
+            let fade_out_from = self.difficulty.preempt * HIDDEN_FADE_OUT_START;
+            let progress = (fade_out_from - lifetime.remaining) / (fade_out_from + slider.duration_in_secs);
+            let mut paint = self.paints.slider.clone();
+            let fade = if lifetime.remaining > fade_out_from {
+                self.splines.hidden_fade.clamped_sample(lifetime.remaining).unwrap()
+            } else {
+                self.splines.hidden_slider_body_fade.clamped_sample(progress).unwrap()
+            };
+            paint.set_alpha_f(paint.alpha_f() * fade);
+            self.canvas.draw_path(&slider.skia_path, &paint);
+        } else {
+            self.canvas.draw_path(&slider.skia_path, &self.paints.slider);
+        }
+        let lifetime = if lifetime.is_dead() || slider.head.is_some() { Lifetime::zero() } else { lifetime };
+        self.draw_circle(pos, lifetime, None, time);
 
+        let circle_scale = self.difficulty.circle_radius / BASE_CIRCLE_RADIUS;
         if let SliderState::DRAGGING(_) = slider.state {
             self.canvas.draw_circle(
                 (pos.x, pos.y),
-                BASE_SLIDER_CIRCLE_RADIUS,
+                BASE_SLIDER_CIRCLE_RADIUS * circle_scale,
                 &self.paints.slider_drag,
             );
         } else if let SliderState::FINISHED(_, finished_at) = slider.state {
@@ -269,28 +671,109 @@ impl<'a> RenderingCtx<'a> {
                 .unwrap();
             self.canvas.draw_circle(
                 (pos.x, pos.y),
-                radius,
+                radius * circle_scale,
                 &self.paints.slider_drag,
             );
         }
     }
 
+    fn draw_spinner(
+        &mut self,
+        spinner: &Spinner,
+        lifetime: &Lifetime,
+        time: &Time,
+    ) {
+        let fade_in = self.splines.circle_life_spline.clamped_sample(lifetime.remaining.max(0.0))
+            .unwrap();
+        // the outer circle closes in until the spinner ends
+        let remaining = if lifetime.is_alive() {
+            1.0
+        } else {
+            let duration = spinner.end_at - lifetime.hit_at;
+            ((spinner.end_at - time.secs_since_start) / duration).max(0.0).min(1.0) as f32
+        };
+
+        let mut paint = self.paints.approach_circle.clone();
+        paint.set_alpha_f(fade_in * paint.alpha_f());
+        self.canvas.draw_circle(SPINNER_CENTER, 10.0 + (SPINNER_RADIUS - 10.0) * remaining, &paint);
+
+        let mut center_paint = self.paints.circle_base_paint.clone();
+        center_paint.set_alpha_f(fade_in);
+        self.canvas.save();
+        self.canvas.translate((SPINNER_CENTER.0 - BASE_CIRCLE_RADIUS, SPINNER_CENTER.1 - BASE_CIRCLE_RADIUS));
+        self.canvas.draw_picture(&self.shapes.circle, None, Some(&center_paint));
+        self.canvas.restore();
+
+        self.draw_text(
+            format!("{} / {}", spinner.spins, spinner.required_spins),
+            Point::new(SPINNER_CENTER.0 - 20.0, SPINNER_CENTER.1 + BASE_CIRCLE_RADIUS + 30.0),
+        );
+    }
+
+    fn draw_judgement_popup(
+        &mut self,
+        popup: &JudgementPopup,
+        pos: &GamePos,
+        time: &Time,
+    ) {
+        let age = time.secs_since_start - popup.spawned_at;
+        let (text, paint) = match popup.judgement {
+            Judgement::Rating(CircleHitRating::GREAT) => ("300", &self.paints.judgement_great),
+            Judgement::Rating(CircleHitRating::GOOD) => ("100", &self.paints.judgement_good),
+            Judgement::Rating(CircleHitRating::MEH) => ("50", &self.paints.judgement_meh),
+            Judgement::Rating(CircleHitRating::MISS) => ("Miss", &self.paints.judgement_miss),
+            Judgement::SliderBreak => ("Slider break", &self.paints.judgement_miss),
+        };
+        let (scale, offset_y) = match popup.judgement {
+            Judgement::Rating(CircleHitRating::MISS) | Judgement::SliderBreak => {
+                (1.0, self.splines.judgement_drop.clamped_sample(age).unwrap())
+            }
+            _ => (self.splines.judgement_scale.clamped_sample(age).unwrap(), 0.0),
+        };
+
+        let mut paint = paint.clone();
+        paint.set_alpha_f(self.splines.judgement_fade.clamped_sample(age).unwrap());
+
+        let (text_width, bounds) = self.fonts.judgement.measure_str(text, None);
+        self.canvas.save();
+        self.canvas.translate((pos.x, pos.y + offset_y));
+        self.canvas.scale((scale, scale));
+        if let Some(blob) = TextBlob::from_str(text, &self.fonts.judgement) {
+            self.canvas.draw_text_blob(blob, (-text_width / 2.0, bounds.height() / 2.0), &paint);
+        }
+        self.canvas.restore();
+    }
+
     fn draw_circle(
         &mut self,
         pos: &GamePos,
         lifetime: &Lifetime,
+        shake: Option<&Shake>,
+        time: &Time,
     ) {
+        let shake_offset = shake
+            .map(|shake| self.splines.shake_offset.clamped_sample(time.secs_since_start - shake.started_at).unwrap())
+            .unwrap_or(0.0);
+
         self.canvas.save();
-        let dead_percentage = self.splines.circle_fade_away_spline.clamped_sample(lifetime.remaining)
-            .unwrap(); // note: reversed %
+        let hidden = self.mods.contains(Mod::Hidden);
+        let dead_percentage = if hidden {
+            self.splines.hidden_fade.clamped_sample(lifetime.remaining).unwrap()
+        } else {
+            self.splines.circle_fade_away_spline.clamped_sample(lifetime.remaining)
+                .unwrap() // note: reversed %
+        };
         let mut paint = self.paints.circle_base_paint.clone();
         paint.set_alpha_f(dead_percentage);
 
-        self.canvas.translate((pos.x - BASE_CIRCLE_RADIUS, pos.y - BASE_CIRCLE_RADIUS));
+        let circle_scale = self.difficulty.circle_radius / BASE_CIRCLE_RADIUS;
+        self.canvas.translate((pos.x + shake_offset, pos.y));
+        self.canvas.scale((circle_scale, circle_scale));
+        self.canvas.translate((-BASE_CIRCLE_RADIUS, -BASE_CIRCLE_RADIUS));
 
         self.canvas.draw_picture(&self.shapes.circle, None, Some(&paint));
 
-        if lifetime.is_alive() {
+        if lifetime.is_alive() && !hidden {
             let alive_percentage = self.splines.circle_life_spline.clamped_sample(lifetime.remaining)
                 .unwrap();
             self.canvas.draw_circle(
@@ -304,14 +787,27 @@ impl<'a> RenderingCtx<'a> {
     }
 }
 
+fn flashlight_radius(combo: u64) -> f32 {
+    if combo < 100 {
+        FLASHLIGHT_RADII[0]
+    } else if combo < 200 {
+        FLASHLIGHT_RADII[1]
+    } else {
+        FLASHLIGHT_RADII[2]
+    }
+}
+
 impl RenderingSystem {
     fn on_resize(&mut self) {
         self.graphics = Graphics::new(&self.window_ctx.clone(), &mut self.gpu_context.clone().borrow_mut());
+        self.fonts = Fonts::new(self.graphics.height_f32 / 720.0);
     }
 
     pub fn new(
         window_ctx: Rc<glutin::ContextWrapper<glutin::PossiblyCurrent, Window>>,
         gpu_context: Rc<RefCell<GpuContext>>,
+        background_path: Option<&Path>,
+        difficulty: PlayDifficulty,
     ) -> Self {
         fn get_default_paint() -> Paint {
             let mut default_paint = Paint::default();
@@ -338,7 +834,8 @@ impl RenderingSystem {
             let mut builder = get_default_paint();
             builder.set_color(Color::from_argb(55, 233, 233, 233));
             builder.set_style(PaintStyle::Stroke);
-            builder.set_stroke_width(60.0);
+            // 60 at the base circle size
+            builder.set_stroke_width(60.0 * difficulty.circle_radius / BASE_CIRCLE_RADIUS);
             builder.set_stroke_join(skia_safe::PaintJoin::Round);
             builder.set_stroke_cap(skia_safe::PaintCap::Round);
             builder
@@ -403,15 +900,28 @@ impl RenderingSystem {
 
         let circle_fade_away_spline = {
             let start = Key::new(0.0, 1.0, Interpolation::Linear);
-            let end = Key::new(-HIT_WINDOW, 0.0, Interpolation::Linear);
+            let end = Key::new(-difficulty.hit_windows.meh, 0.0, Interpolation::Linear);
             Spline::from_vec(vec![start, end])
         };
         let circle_life_spline = {
-            let start = Key::new(LIFETIME, 0.0, Interpolation::Linear);
+            let start = Key::new(difficulty.preempt, 0.0, Interpolation::Linear);
             let end = Key::new(0.0, 1.0, Interpolation::Linear);
             Spline::from_vec(vec![start, end])
         };
 
+        // fade in over the first 40% of the approach, out over the next 30%
+        let hidden_fade = {
+            let start = Key::new(difficulty.preempt, 0.0, Interpolation::Linear);
+            let faded_in = Key::new(difficulty.preempt * HIDDEN_FADE_OUT_START, 1.0, Interpolation::Linear);
+            let faded_out = Key::new(difficulty.preempt * HIDDEN_FADE_OUT_END, 0.0, Interpolation::Linear);
+            Spline::from_vec(vec![start, faded_in, faded_out])
+        };
+        let hidden_slider_body_fade = {
+            let start = Key::new(0.0, 1.0, Interpolation::Linear);
+            let end = Key::new(1.0, 0.0, Interpolation::Linear);
+            Spline::from_vec(vec![start, end])
+        };
+
         let slider_hold_circle = {
             let start = Key::new(0.0, BASE_SLIDER_CIRCLE_RADIUS, Interpolation::Linear);
             // not animating for a while
@@ -427,13 +937,86 @@ impl RenderingSystem {
             Spline::from_vec(vec![start, end])
         };
 
-        let default_font = {
-            let typeface = Typeface::new("Verdana", FontStyle::new(Weight::NORMAL, Width::NORMAL, Slant::Upright))
-                .unwrap();
-            Font::new(typeface, 18.0)
+        let hit_error_tick_fade = {
+            let start = Key::new(0.0, 1.0, Interpolation::Linear);
+            let end = Key::new(HIT_ERROR_TICK_LIFETIME, 0.0, Interpolation::Linear);
+            Spline::from_vec(vec![start, end])
+        };
+
+        let judgement_fade = {
+            let start = Key::new(0.0, 1.0, Interpolation::Linear);
+            let hold = Key::new(JUDGEMENT_POPUP_LIFETIME * 0.6, 1.0, Interpolation::Linear);
+            let end = Key::new(JUDGEMENT_POPUP_LIFETIME, 0.0, Interpolation::Linear);
+            Spline::from_vec(vec![start, hold, end])
         };
 
+        let judgement_scale = {
+            let start = Key::new(0.0, 0.6, Interpolation::Linear);
+            let overshoot = Key::new(0.080, 1.15, Interpolation::Linear);
+            let end = Key::new(0.160, 1.0, Interpolation::Linear);
+            Spline::from_vec(vec![start, overshoot, end])
+        };
+
+        let judgement_drop = {
+            let start = Key::new(0.0, 0.0, Interpolation::Linear);
+            let end = Key::new(JUDGEMENT_POPUP_LIFETIME, 20.0, Interpolation::Linear);
+            Spline::from_vec(vec![start, end])
+        };
+
+        // a few quick swings left and right
+        let shake_offset = {
+            let keys = [(0.0, 0.0), (0.030, 6.0), (0.090, -6.0), (0.150, 4.0), (0.210, -2.0), (0.250, 0.0)];
+            Spline::from_vec(keys.iter().map(|&(t, x)| Key::new(t, x, Interpolation::Linear)).collect())
+        };
+
+        let background_dim = {
+            let mut builder = get_default_paint();
+            builder.set_alpha(60);
+            builder
+        };
+
+        let overlay = {
+            let mut builder = get_default_paint();
+            builder.set_color(Color::from_argb(180, 0, 0, 0));
+            builder.set_style(PaintStyle::Fill);
+            builder
+        };
+
+        let replay_cursor = {
+            let mut builder = get_default_paint();
+            builder.set_color(Color::from_rgb(252, 163, 17));
+            builder.set_style(PaintStyle::Stroke);
+            builder.set_stroke_width(3.0);
+            builder
+        };
+
+        let hud_progress = {
+            let mut builder = get_default_paint();
+            builder.set_color(Color::from_rgb(211, 211, 211));
+            builder.set_style(PaintStyle::Fill);
+            builder
+        };
+
+        fn judgement_paint(color: Color) -> Paint {
+            let mut builder = Paint::default();
+            builder.set_anti_alias(true);
+            builder.set_color(color);
+            builder.set_style(PaintStyle::Fill);
+            builder
+        }
+
+        let background = background_path.and_then(|path| {
+            let image = std::fs::read(path)
+                .ok()
+                .and_then(|bytes| Image::from_encoded(Data::new_copy(&bytes), None));
+            if image.is_none() {
+                log::warn!("Failed to load background {:?}", path);
+            }
+            image
+        });
+
         let graphics = Graphics::new(&window_ctx.clone(), &mut gpu_context.clone().borrow_mut());
+        let fonts = Fonts::new(graphics.height_f32 / 720.0);
 
 
         let circle = {
@@ -483,16 +1066,31 @@ impl RenderingSystem {
                 key_cap_off,
                 slider,
                 slider_drag,
+                background_dim,
+                overlay,
+                hud_progress,
+                replay_cursor,
+                judgement_great: judgement_paint(Color::from_rgb(102, 204, 255)),
+                judgement_good: judgement_paint(Color::from_rgb(136, 221, 68)),
+                judgement_meh: judgement_paint(Color::from_rgb(255, 204, 34)),
+                judgement_miss: judgement_paint(Color::from_rgb(255, 68, 68)),
             },
             splines: Splines {
                 circle_fade_away_spline,
                 circle_life_spline,
                 key_cap_light_on_spline,
                 slider_hold_circle,
+                hit_error_tick_fade,
+                judgement_fade,
+                judgement_scale,
+                judgement_drop,
+                shake_offset,
+                hidden_fade,
+                hidden_slider_body_fade,
             },
-            fonts: Fonts {
-                default: default_font,
-            },
+            fonts,
+            background,
+            difficulty,
         };
     }
 }
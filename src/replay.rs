@@ -0,0 +1,252 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::difficulty::DifficultyAdjust;
+use crate::mods::Mods;
+use crate::resources::GameInputKeyBinding;
+use crate::scoring::ScoringMode;
+
+pub const REPLAYS_FOLDER: &'static str = "replays";
+
+const FRAMES_SECTION: &'static str = "[Frames]";
+
+// gameplay input, positions are in game area coordinates so replays play back at any window size
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ReplayInput {
+    CursorMoved(f32, f32),
+    KeyPressed(GameInputKeyBinding),
+    KeyReleased(GameInputKeyBinding),
+    // a frame without input ended, only recorded right before a frame with input
+    Idle,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ReplayFrame {
+    // gameplay time
+    pub at: f64,
+    pub input: ReplayInput,
+}
+
+// a recorded play, `Key = Value` lines followed by one frame per line
+#[derive(Debug, Clone, PartialEq)]
+pub struct Replay {
+    pub beatmap_hash: String,
+    pub beatmap_label: String,
+    pub mods: Mods,
+    pub difficulty_adjust: DifficultyAdjust,
+    pub scoring_mode: ScoringMode,
+    pub score: u64,
+    pub frames: Vec<ReplayFrame>,
+}
+
+#[derive(Debug)]
+pub struct ReplayParseError(String);
+
+impl fmt::Display for ReplayParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid replay: {}", self.0)
+    }
+}
+
+impl Replay {
+    // saves to the replays folder, returns where it went
+    pub fn save(&self) -> std::io::Result<PathBuf> {
+        std::fs::create_dir_all(REPLAYS_FOLDER)?;
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|it| it.as_secs())
+            .unwrap_or(0);
+        let path = Path::new(REPLAYS_FOLDER).join(format!("{}-{}.replay", self.beatmap_hash, secs));
+        std::fs::write(&path, self.serialize())?;
+        Ok(path)
+    }
+
+    pub fn load(path: &Path) -> Result<Replay, ReplayParseError> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|err| ReplayParseError(format!("{}: {}", path.display(), err)))?;
+        Replay::parse(&text)
+    }
+
+    fn serialize(&self) -> String {
+        let adjust = &self.difficulty_adjust;
+        let mut text = String::new();
+        text.push_str(&format!("Beatmap = {}\n", self.beatmap_hash));
+        text.push_str(&format!("Label = {}\n", self.beatmap_label));
+        text.push_str(&format!("Mods = {}\n", self.mods));
+        text.push_str(&format!("Adjust = {},{},{},{},{}\n",
+            setting_to_string(adjust.circle_size),
+            setting_to_string(adjust.approach_rate),
+            setting_to_string(adjust.overall_difficulty),
+            setting_to_string(adjust.hp_drain_rate),
+            setting_to_string(adjust.rate),
+        ));
+        text.push_str(&format!("Scoring = {}\n", match self.scoring_mode {
+            ScoringMode::V1 => "V1",
+            ScoringMode::V2 => "V2",
+        }));
+        text.push_str(&format!("Score = {}\n", self.score));
+        text.push_str(FRAMES_SECTION);
+        text.push('\n');
+        for frame in self.frames.iter() {
+            let line = match frame.input {
+                ReplayInput::CursorMoved(x, y) => format!("{},M,{},{}\n", frame.at, x, y),
+                ReplayInput::KeyPressed(binding) => format!("{},P,{}\n", frame.at, binding_to_str(binding)),
+                ReplayInput::KeyReleased(binding) => format!("{},R,{}\n", frame.at, binding_to_str(binding)),
+                ReplayInput::Idle => format!("{},I\n", frame.at),
+            };
+            text.push_str(&line);
+        }
+        text
+    }
+
+    fn parse(text: &str) -> Result<Replay, ReplayParseError> {
+        let err = |what: &str| ReplayParseError(what.to_string());
+        let mut replay = Replay {
+            beatmap_hash: String::new(),
+            beatmap_label: String::new(),
+            mods: Mods::default(),
+            difficulty_adjust: DifficultyAdjust::default(),
+            scoring_mode: ScoringMode::V1,
+            score: 0,
+            frames: Vec::new(),
+        };
+
+        let mut lines = text.lines();
+        for line in &mut lines {
+            if line.trim() == FRAMES_SECTION {
+                break;
+            }
+            let mut key_value = line.splitn(2, "=").map(str::trim);
+            let (key, value) = match (key_value.next(), key_value.next()) {
+                (Some(key), Some(value)) => (key, value),
+                _ => continue,
+            };
+            match key {
+                "Beatmap" => replay.beatmap_hash = value.to_string(),
+                "Label" => replay.beatmap_label = value.to_string(),
+                "Mods" if value == "NM" => {}
+                "Mods" => replay.mods = Mods::from_str(value).map_err(|it| err(&it.to_string()))?,
+                "Adjust" => {
+                    let settings = value.split(',')
+                        .map(setting_from_str)
+                        .collect::<Option<Vec<_>>>()
+                        .filter(|it| it.len() == 5)
+                        .ok_or_else(|| err("bad difficulty adjust"))?;
+                    replay.difficulty_adjust = DifficultyAdjust::new(settings[0], settings[1], settings[2], settings[3], settings[4])
+                        .map_err(|it| err(&it.to_string()))?;
+                }
+                "Scoring" => replay.scoring_mode = match value {
+                    "V1" => ScoringMode::V1,
+                    "V2" => ScoringMode::V2,
+                    _ => return Err(err("bad scoring mode")),
+                },
+                "Score" => replay.score = u64::from_str(value).map_err(|_| err("bad score"))?,
+                _ => log::warn!("Replay: ignoring unknown key {}", key),
+            }
+        }
+
+        for line in lines {
+            let parts = line.split(',').collect::<Vec<_>>();
+            let frame = match parts[..] {
+                [at, "M", x, y] => f32::from_str(x).ok()
+                    .zip(f32::from_str(y).ok())
+                    .map(|(x, y)| (at, ReplayInput::CursorMoved(x, y))),
+                [at, "P", binding] => binding_from_str(binding).map(|it| (at, ReplayInput::KeyPressed(it))),
+                [at, "R", binding] => binding_from_str(binding).map(|it| (at, ReplayInput::KeyReleased(it))),
+                [at, "I"] => Some((at, ReplayInput::Idle)),
+                _ => None,
+            };
+            let (at, input) = frame.ok_or_else(|| err(&format!("bad frame {}", line)))?;
+            let at = f64::from_str(at).map_err(|_| err(&format!("bad frame time {}", line)))?;
+            replay.frames.push(ReplayFrame { at, input });
+        }
+
+        if replay.beatmap_hash.is_empty() {
+            return Err(err("no beatmap hash"));
+        }
+        Ok(replay)
+    }
+}
+
+fn binding_to_str(binding: GameInputKeyBinding) -> &'static str {
+    match binding {
+        GameInputKeyBinding::Hit1 => "Hit1",
+        GameInputKeyBinding::Hit2 => "Hit2",
+    }
+}
+
+fn binding_from_str(s: &str) -> Option<GameInputKeyBinding> {
+    match s {
+        "Hit1" => Some(GameInputKeyBinding::Hit1),
+        "Hit2" => Some(GameInputKeyBinding::Hit2),
+        _ => None,
+    }
+}
+
+fn setting_to_string(value: Option<f64>) -> String {
+    value.map(|it| it.to_string()).unwrap_or_else(|| "-".to_string())
+}
+
+// "-" when not set, None when invalid
+fn setting_from_str(s: &str) -> Option<Option<f64>> {
+    if s == "-" {
+        Some(None)
+    } else {
+        f64::from_str(s).ok().map(Some)
+    }
+}
+
+#[cfg(test)]
+fn test_replay() -> Replay {
+    Replay {
+        beatmap_hash: "abc".to_string(),
+        beatmap_label: "Artist - Title [Hard]".to_string(),
+        mods: Mods::from_str("HDDT").unwrap(),
+        difficulty_adjust: DifficultyAdjust::new(None, Some(10.3), None, None, None).unwrap(),
+        scoring_mode: ScoringMode::V2,
+        score: 123456,
+        frames: vec![
+            ReplayFrame { at: -0.5, input: ReplayInput::CursorMoved(256.0, 192.5) },
+            ReplayFrame { at: 0.99, input: ReplayInput::Idle },
+            ReplayFrame { at: 1.0, input: ReplayInput::CursorMoved(100.0, 100.0) },
+            ReplayFrame { at: 1.0, input: ReplayInput::KeyPressed(GameInputKeyBinding::Hit1) },
+            ReplayFrame { at: 1.0166666, input: ReplayInput::KeyReleased(GameInputKeyBinding::Hit1) },
+        ],
+    }
+}
+
+#[test]
+fn replay_roundtrip_test() {
+    let replay = test_replay();
+    assert_eq!(Replay::parse(&replay.serialize()).unwrap(), replay);
+    assert!(Replay::parse("Score = 1\n").is_err());
+}
+
+#[test]
+fn replay_playback_test() {
+    use crate::resources::ReplayPlayback;
+
+    let replay = test_replay();
+    let mut playback = ReplayPlayback::new(&replay, 1.0);
+
+    // one recorded frame at a time, its input was all recorded at the same time
+    assert_eq!(playback.next_frame(), Some(replay.frames[0]));
+    playback.take_frame();
+    assert_eq!(playback.due, vec![replay.frames[0].input]);
+    // a frame without input only tells where the next one started
+    assert_eq!(playback.next_frame(), Some(replay.frames[1]));
+    playback.take_frame();
+    assert!(playback.due.is_empty());
+    playback.take_frame();
+    assert_eq!(playback.due, vec![replay.frames[2].input, replay.frames[3].input]);
+    playback.take_frame();
+    assert_eq!(playback.due, vec![replay.frames[4].input]);
+    assert_eq!(playback.next_frame(), None);
+
+    // a restart, like seeking does, plays the same frames from the start
+    playback.restart();
+    assert_eq!(playback.next_frame(), Some(replay.frames[0]));
+    playback.take_frame();
+    assert_eq!(playback.due, vec![replay.frames[0].input]);
+}
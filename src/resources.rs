@@ -9,9 +9,13 @@ use skia_safe::gpu::gl::FramebufferInfo;
 use std::collections::{VecDeque, HashMap, HashSet};
 use std::collections::vec_deque::Iter;
 use crate::{utils::{Timer, max_f64, min_f64}};
-use crate::consts::{TRIAL_POINTS, TRAIL_SAMPLE_EACH};
+use crate::consts::{TRIAL_POINTS, TRAIL_SAMPLE_EACH, AUDIO_STALL_THRESHOLD, AUDIO_RESYNC_THRESHOLD, AUDIO_DRIFT_CORRECTION, HIT_ERROR_METER_TICKS};
 use glutin::event::VirtualKeyCode;
-use crate::components::{SliderStateChange};
+use crate::components::{CircleHitRating, SliderCheckpointKind, SliderStateChange};
+use crate::scoring::{ScoreBonus, ScoreV1, ScoringMode, ScoringModel};
+use crate::mods::Mods;
+use crate::difficulty::HitWindows;
+use crate::replay::{Replay, ReplayFrame, ReplayInput};
 
 #[derive(Debug, Default)]
 pub struct GameCursor {
@@ -23,13 +27,38 @@ pub struct GameCursor {
 pub struct Hp {
     pub value: f64,
     pub drain: f64,
+    // full refills left once the bar runs empty
+    pub lives: u32,
 }
 
 impl Hp {
+    pub fn new(drain: f64, lives: u32) -> Self {
+        Self {
+            value: 1.0,
+            drain,
+            lives,
+        }
+    }
+
     pub fn drain(&mut self) {
         self.value -= self.drain;
         self.value = max_f64(self.value, 0.0);
+        if self.value <= 0.0 && self.lives > 0 {
+            self.lives -= 1;
+            self.value = 1.0;
+        }
     }
+
+    // empties the bar for good, used by sudden death and perfect
+    pub fn fail(&mut self) {
+        self.value = 0.0;
+        self.lives = 0;
+    }
+
+    pub fn failed(&self) -> bool {
+        self.value <= 0.0
+    }
+
     pub fn fill(&mut self) {
         self.value += self.drain;
         self.value = min_f64(self.value, 1.0);
@@ -38,10 +67,7 @@ impl Hp {
 
 impl Default for Hp {
     fn default() -> Self {
-        return Self {
-            value: 1.0,
-            drain: 0.1,
-        };
+        Hp::new(0.1, 0)
     }
 }
 
@@ -88,12 +114,168 @@ impl Default for TrailTimer {
 #[derive(Debug)]
 pub struct CircleSpawnerData {}
 
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PauseMenuItem {
+    Continue,
+    Retry,
+    Quit,
+}
+
+impl PauseMenuItem {
+    pub fn label(&self) -> &'static str {
+        match self {
+            PauseMenuItem::Continue => "Continue",
+            PauseMenuItem::Retry => "Retry",
+            PauseMenuItem::Quit => "Quit",
+        }
+    }
+}
+
+pub const PAUSE_MENU_ITEMS: [PauseMenuItem; 3] = [
+    PauseMenuItem::Continue,
+    PauseMenuItem::Retry,
+    PauseMenuItem::Quit,
+];
+
+#[derive(Debug, Default)]
+pub struct PauseMenu {
+    pub active: bool,
+    pub selected_index: usize,
+}
+
+impl PauseMenu {
+    pub fn open(&mut self) {
+        self.active = true;
+        self.selected_index = 0;
+    }
+
+    pub fn close(&mut self) {
+        self.active = false;
+    }
+
+    pub fn select_next(&mut self) {
+        self.selected_index = (self.selected_index + 1) % PAUSE_MENU_ITEMS.len();
+    }
+
+    pub fn select_previous(&mut self) {
+        self.selected_index = (self.selected_index + PAUSE_MENU_ITEMS.len() - 1) % PAUSE_MENU_ITEMS.len();
+    }
+
+    pub fn selected(&self) -> PauseMenuItem {
+        PAUSE_MENU_ITEMS[self.selected_index]
+    }
+}
+
+
 #[derive(Debug)]
 pub struct Time {
     pub delta: Duration,
     pub delta_seconds: f64,
     pub secs_since_start: f64,
     pub now: Instant,
+    // gameplay time at which the song starts playing (lead in & audio offset)
+    pub song_start: f64,
+    pub paused: bool,
+    // song speed, gameplay time is song time and runs this much faster than the wall clock
+    pub rate: f64,
+    // where the clock is, ahead of `secs_since_start` while a replay holds the frame back
+    clock: f64,
+    // where the last frame ended, kept as is rather than worked out from the delta
+    frame_start: f64,
+}
+
+impl Time {
+    pub fn starting_song_at(song_start: f64) -> Self {
+        Time {
+            secs_since_start: min_f64(song_start, 0.0),
+            song_start,
+            clock: min_f64(song_start, 0.0),
+            frame_start: min_f64(song_start, 0.0),
+            ..Time::default()
+        }
+    }
+
+    pub fn with_rate(self, rate: f64) -> Self {
+        Time {
+            rate,
+            ..self
+        }
+    }
+
+    // moves gameplay time forward, following the song position
+    // whenever the song is playing
+    pub fn advance(&mut self, elapsed: Duration, song_clock: &SongClock) {
+        let now = Instant::now();
+        let wall_elapsed = elapsed.as_secs_f64() * self.rate;
+
+        let next = if self.paused {
+            self.clock
+        } else {
+            match song_clock.state {
+                SongClockState::NotStarted | SongClockState::Finished => {
+                    self.clock + wall_elapsed
+                }
+                SongClockState::Paused => {
+                    self.clock
+                }
+                SongClockState::Playing => {
+                    let since_reported = now.duration_since(song_clock.position_changed_at)
+                        .as_secs_f64();
+                    if since_reported > AUDIO_STALL_THRESHOLD {
+                        // the audio thread stopped making progress, wait for it
+                        self.clock
+                    } else {
+                        // the reported position only changes once per audio buffer,
+                        // so extrapolate it and nudge our own clock towards it
+                        let audio_time = self.song_start + song_clock.position + since_reported * self.rate;
+                        let predicted = self.clock + wall_elapsed;
+                        let drift = audio_time - predicted;
+                        if drift.abs() > AUDIO_RESYNC_THRESHOLD {
+                            audio_time
+                        } else {
+                            predicted + drift * AUDIO_DRIFT_CORRECTION
+                        }
+                    }
+                }
+            }
+        };
+
+        // never run backwards
+        let next = max_f64(next, self.clock);
+
+        self.frame_start = self.secs_since_start;
+        self.delta_seconds = next - self.secs_since_start;
+        self.delta = Duration::from_secs_f64(self.delta_seconds);
+        self.secs_since_start = next;
+        self.clock = next;
+        self.now = now;
+    }
+
+    pub fn frame_start(&self) -> f64 {
+        self.frame_start
+    }
+
+    pub fn clock(&self) -> f64 {
+        self.clock
+    }
+
+    // another frame right after this one, up to the clock, for the time a replay held back
+    pub fn catch_up(&mut self) {
+        self.frame_start = self.secs_since_start;
+        self.delta_seconds = self.clock - self.secs_since_start;
+        self.delta = Duration::from_secs_f64(self.delta_seconds);
+        self.secs_since_start = self.clock;
+    }
+
+    // ends this frame at `at` instead, replays use it to land on the recorded frames.
+    // the clock keeps going, the time held back is part of the next frame
+    pub fn set_frame_end(&mut self, at: f64) {
+        let at = max_f64(at, self.frame_start);
+        self.delta_seconds = at - self.frame_start;
+        self.delta = Duration::from_secs_f64(self.delta_seconds);
+        self.secs_since_start = at;
+        self.clock = max_f64(self.clock, at);
+    }
 }
 
 impl Default for Time {
@@ -103,6 +285,50 @@ impl Default for Time {
             delta_seconds: 0.0,
             secs_since_start: 0.0,
             now: Instant::now(),
+            song_start: 0.0,
+            paused: false,
+            rate: 1.0,
+            clock: 0.0,
+            frame_start: 0.0,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SongClockState {
+    NotStarted,
+    Playing,
+    Paused,
+    Finished,
+}
+
+// playback state of the song as reported by kira,
+// the source of truth for gameplay time
+#[derive(Debug)]
+pub struct SongClock {
+    pub state: SongClockState,
+    // song position in seconds
+    pub position: f64,
+    // when the audio thread last moved the position
+    pub position_changed_at: Instant,
+}
+
+impl SongClock {
+    pub fn report(&mut self, state: SongClockState, position: f64) {
+        if self.position != position || self.state != state {
+            self.position_changed_at = Instant::now();
+        }
+        self.state = state;
+        self.position = position;
+    }
+}
+
+impl Default for SongClock {
+    fn default() -> Self {
+        SongClock {
+            state: SongClockState::NotStarted,
+            position: 0.0,
+            position_changed_at: Instant::now(),
         }
     }
 }
@@ -119,6 +345,11 @@ impl GameArea {
         (p.x, p.y)
     }
 
+    pub fn screen_cords_to_game(&self, xy: (f32, f32)) -> (f32, f32) {
+        let p = self.matrix.unwrap().invert().unwrap().map_point(xy);
+        (p.x, p.y)
+    }
+
     pub fn set_game_area_matrix(&mut self, new_matrix: skia_safe::matrix::Matrix) {
         assert_eq!(new_matrix.scale_x(), new_matrix.scale_y(), "Canvas x/y scale must be the same");
         self.scale = new_matrix.scale_x();
@@ -128,6 +359,11 @@ impl GameArea {
     pub fn scale(&self) -> f32 {
         self.scale
     }
+
+    // only known once the first frame has been drawn
+    pub fn has_matrix(&self) -> bool {
+        self.matrix.is_some()
+    }
 }
 
 #[derive(Debug, Default)]
@@ -145,15 +381,24 @@ pub struct GameInputState {
 
 }
 
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum GameInputKeyBinding {
     Hit1,
     Hit2,
 }
 
-const fn key_vk_for_key_binding(kb: &'static GameInputKeyBinding) -> &'static VirtualKeyCode {
+pub const HIT_BINDINGS: [GameInputKeyBinding; 2] = [GameInputKeyBinding::Hit1, GameInputKeyBinding::Hit2];
+
+pub fn key_binding_for_vk(key: &VirtualKeyCode) -> Option<GameInputKeyBinding> {
+    HIT_BINDINGS.iter()
+        .find(|binding| key_vk_for_key_binding(**binding) == *key)
+        .cloned()
+}
+
+pub const fn key_vk_for_key_binding(kb: GameInputKeyBinding) -> VirtualKeyCode {
     match kb {
-        GameInputKeyBinding::Hit1 => &VirtualKeyCode::G,
-        GameInputKeyBinding::Hit2 => &VirtualKeyCode::H,
+        GameInputKeyBinding::Hit1 => VirtualKeyCode::G,
+        GameInputKeyBinding::Hit2 => VirtualKeyCode::H,
     }
 }
 
@@ -163,16 +408,105 @@ impl GameInputState {
     }
 
     pub fn is_key_active(&self, bind: &'static GameInputKeyBinding) -> bool {
-        self.active_set.contains(key_vk_for_key_binding(bind))
+        self.active_set.contains(&key_vk_for_key_binding(*bind))
     }
 
     pub fn is_key_hold(&self, bind: &'static GameInputKeyBinding) -> bool {
-        self.hold_set.contains(key_vk_for_key_binding(bind))
+        self.hold_set.contains(&key_vk_for_key_binding(*bind))
     }
 
     pub fn last_pressed_at(&self, bind: &'static GameInputKeyBinding) -> Option<Instant> {
-        let key = key_vk_for_key_binding(bind);
-        self.last_active_keys_map.get(key).cloned()
+        let key = key_vk_for_key_binding(*bind);
+        self.last_active_keys_map.get(&key).cloned()
+    }
+}
+
+// input of the current attempt, saved as a replay once the map ends
+#[derive(Debug, Default)]
+pub struct ReplayRecorder {
+    pub frames: Vec<ReplayFrame>,
+}
+
+impl ReplayRecorder {
+    // input is recorded with the end of the frame it came in. frames without any input
+    // aren't recorded, except for the one right before a frame with input, as
+    // `ReplayInput::Idle`, so playback knows where the frame with input started
+    pub fn record(&mut self, time: &Time, input: ReplayInput) {
+        let frame_start = time.frame_start();
+        let previous_recorded = matches!(self.frames.last(), Some(last) if last.at >= frame_start);
+        if !previous_recorded && frame_start < time.secs_since_start {
+            self.frames.push(ReplayFrame { at: frame_start, input: ReplayInput::Idle });
+        }
+        self.frames.push(ReplayFrame { at: time.secs_since_start, input });
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+}
+
+// plays a replay back instead of taking the player's input
+#[derive(Debug)]
+pub struct ReplayPlayback {
+    frames: Vec<ReplayFrame>,
+    next_frame: usize,
+    // recorded input to feed through this frame
+    pub due: Vec<ReplayInput>,
+    // on top of the rate of the replay's mods
+    pub speed: f64,
+    pub base_rate: f64,
+    // fast forwarding to this gameplay time, the song waits until it's reached
+    pub seeking_to: Option<f64>,
+    // the next recorded frame isn't due yet, gameplay sits this frame out
+    pub holding: bool,
+    // recorded frames are due already, gameplay runs them right after this one
+    pub catching_up: bool,
+}
+
+impl ReplayPlayback {
+    pub fn new(replay: &Replay, base_rate: f64) -> Self {
+        Self {
+            frames: replay.frames.clone(),
+            next_frame: 0,
+            due: Vec::new(),
+            speed: 1.0,
+            base_rate,
+            seeking_to: None,
+            holding: false,
+            catching_up: false,
+        }
+    }
+
+    pub fn restart(&mut self) {
+        self.next_frame = 0;
+        self.due.clear();
+        self.holding = false;
+        self.catching_up = false;
+    }
+
+    pub fn is_seeking(&self) -> bool {
+        self.seeking_to.is_some()
+    }
+
+    // the first input of the next recorded frame, None once the replay is over
+    pub fn next_frame(&self) -> Option<ReplayFrame> {
+        self.frames.get(self.next_frame).copied()
+    }
+
+    // moves the input of the next recorded frame into `due`,
+    // all input of a frame was recorded at the same time
+    pub fn take_frame(&mut self) {
+        self.due.clear();
+        let at = match self.next_frame() {
+            Some(frame) => frame.at,
+            None => return,
+        };
+        while let Some(frame) = self.frames.get(self.next_frame).filter(|frame| frame.at == at) {
+            if frame.input != ReplayInput::Idle {
+                self.due.push(frame.input);
+            }
+            self.next_frame += 1;
+        }
     }
 }
 
@@ -190,26 +524,258 @@ impl Combo {
     }
 }
 
-#[derive(Default)]
 pub struct Score {
-    pub value: u64,
+    pub mode: ScoringMode,
+    model: Box<dyn ScoringModel + Send + Sync>,
+}
+
+impl Default for Score {
+    fn default() -> Self {
+        Score::new(ScoringMode::V1, Box::new(ScoreV1::new(1.0, 1.0)))
+    }
 }
 
 impl Score {
-    pub fn on_good(&mut self, c: &Combo) {
-        self.value += c.value * 100;
+    pub fn new(mode: ScoringMode, model: Box<dyn ScoringModel + Send + Sync>) -> Self {
+        Self {
+            mode,
+            model,
+        }
+    }
+    pub fn value(&self) -> u64 {
+        self.model.value()
+    }
+    // `c` being the combo before this hit
+    pub fn on_hit(&mut self, hit_value: u64, c: &Combo) {
+        self.model.on_hit(hit_value, c.value);
     }
-    pub fn on_great(&mut self, c: &Combo) {
-        self.value += c.value * 300;
+    // slider ticks and spinner spins, the combo doesn't matter for them
+    pub fn on_bonus(&mut self, bonus: ScoreBonus) {
+        self.model.on_bonus(bonus);
+    }
+    pub fn reset(&mut self) {
+        self.model.reset();
     }
 }
 
+// how far the play has come, used to tell when the map is over
+#[derive(Debug, Default)]
+pub struct MapProgress {
+    pub all_objects_spawned: bool,
+    pub finished_at: Option<f64>,
+    pub first_object_at: f64,
+    pub last_object_at: f64,
+}
+
+impl MapProgress {
+    pub fn new((first_object_at, last_object_at): (f64, f64)) -> Self {
+        Self {
+            first_object_at,
+            last_object_at,
+            ..MapProgress::default()
+        }
+    }
+
+    pub fn restart(&mut self) {
+        self.all_objects_spawned = false;
+        self.finished_at = None;
+    }
+
+    // 0.0 - 1.0 between the first and the last object
+    pub fn fraction(&self, time: &Time) -> f64 {
+        let length = self.last_object_at - self.first_object_at;
+        if length <= 0.0 {
+            return 0.0;
+        }
+        ((time.secs_since_start - self.first_object_at) / length)
+            .max(0.0)
+            .min(1.0)
+    }
+}
+
+// judgements of the play so far
+#[derive(Debug, Default, Clone)]
+pub struct PlayStats {
+    pub great: u32,
+    pub good: u32,
+    pub meh: u32,
+    pub miss: u32,
+    pub slider_breaks: u32,
+    pub max_combo: u64,
+    // see `DespawnObject::hit_error`
+    pub hit_errors: Vec<f64>,
+}
+
+impl PlayStats {
+    pub fn record(&mut self, rating: CircleHitRating) {
+        match rating {
+            CircleHitRating::MISS => self.miss += 1,
+            CircleHitRating::MEH => self.meh += 1,
+            CircleHitRating::GOOD => self.good += 1,
+            CircleHitRating::GREAT => self.great += 1,
+        }
+    }
+
+    pub fn judged_count(&self) -> u32 {
+        self.great + self.good + self.meh + self.miss
+    }
+
+    // 0.0 - 1.0, a play without judgements counts as perfect
+    pub fn accuracy(&self) -> f64 {
+        let judged = self.judged_count();
+        if judged == 0 {
+            return 1.0;
+        }
+        let points = self.great * 300 + self.good * 100 + self.meh * 50;
+        points as f64 / (judged * 300) as f64
+    }
+
+    pub fn grade(&self) -> Grade {
+        Grade::from_accuracy(self.accuracy())
+    }
+
+    // 10 times the standard deviation of the hit errors in milliseconds
+    pub fn unstable_rate(&self) -> Option<f64> {
+        if self.hit_errors.is_empty() {
+            return None;
+        }
+        let count = self.hit_errors.len() as f64;
+        let mean = self.hit_errors.iter().sum::<f64>() / count;
+        let variance = self.hit_errors.iter()
+            .map(|error| (error - mean).powi(2))
+            .sum::<f64>() / count;
+        Some(variance.sqrt() * 1000.0 * 10.0)
+    }
+
+    // hit errors counted into `bin_count` equal bins spanning `-window..window`
+    pub fn hit_error_histogram(&self, window: f64, bin_count: usize) -> Vec<u32> {
+        let mut bins = vec![0; bin_count];
+        let bin_width = window * 2.0 / bin_count as f64;
+        for error in self.hit_errors.iter() {
+            let bin = ((error + window) / bin_width).floor();
+            if bin >= 0.0 && (bin as usize) < bin_count {
+                bins[bin as usize] += 1;
+            }
+        }
+        bins
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct HitErrorTick {
+    pub error: f64,
+    pub at: f64,
+}
+
+// the most recent hit errors, shown on the hit error meter
+#[derive(Debug, Default)]
+pub struct HitErrorMeter {
+    ticks: VecDeque<HitErrorTick>,
+}
+
+impl HitErrorMeter {
+    pub fn push(&mut self, error: f64, at: f64) {
+        if self.ticks.len() == HIT_ERROR_METER_TICKS {
+            self.ticks.pop_front();
+        }
+        self.ticks.push_back(HitErrorTick { error, at });
+    }
+
+    pub fn iter(&self) -> Iter<'_, HitErrorTick> {
+        self.ticks.iter()
+    }
+
+    pub fn clear(&mut self) {
+        self.ticks.clear();
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Grade {
+    SS,
+    S,
+    A,
+    B,
+    C,
+    D,
+}
+
+impl Grade {
+    pub fn from_accuracy(accuracy: f64) -> Grade {
+        if accuracy >= 1.0 {
+            Grade::SS
+        } else if accuracy >= 0.95 {
+            Grade::S
+        } else if accuracy >= 0.90 {
+            Grade::A
+        } else if accuracy >= 0.80 {
+            Grade::B
+        } else if accuracy >= 0.70 {
+            Grade::C
+        } else {
+            Grade::D
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Grade::SS => "SS",
+            Grade::S => "S",
+            Grade::A => "A",
+            Grade::B => "B",
+            Grade::C => "C",
+            Grade::D => "D",
+        }
+    }
+}
+
+#[test]
+fn grade_test() {
+    let mut stats = PlayStats::default();
+    assert_eq!(stats.grade(), Grade::SS);
+
+    stats.great = 18;
+    stats.good = 1;
+    stats.miss = 1;
+    // (18 * 300 + 100) / (20 * 300)
+    assert!((stats.accuracy() - 0.9166).abs() < 0.001);
+    assert_eq!(stats.grade(), Grade::A);
+
+    stats.miss = 10;
+    assert_eq!(stats.grade(), Grade::D);
+
+    assert_eq!(stats.unstable_rate(), None);
+    stats.hit_errors = vec![-0.010, 0.010];
+    assert!((stats.unstable_rate().unwrap() - 100.0).abs() < 1e-9);
+}
+
+// outcome of a finished play, shown on the results screen
+#[derive(Debug, Clone)]
+pub struct PlayResult {
+    pub beatmap_label: String,
+    pub mods: Mods,
+    pub score: u64,
+    pub scoring_mode: ScoringMode,
+    pub stats: PlayStats,
+    pub hit_windows: HitWindows,
+    pub failed: bool,
+}
+
 #[derive(PartialOrd, PartialEq)]
 pub enum GameEvent {
     SongLoad(String),
     WindowResized((u32, u32)),
     SliderStart,
     SliderStop,
+    Pause,
+    Resume,
+    Restart,
+    // rating of a slider head, its hit error and where the head is
+    SliderHead(CircleHitRating, Option<f64>, (f32, f32)),
+    // whether the slider was held at that point and where the slider ball was
+    SliderCheckpoint(SliderCheckpointKind, bool, (f32, f32)),
+    // whether it was a bonus spin
+    SpinnerSpin(bool),
 }
 
 #[derive(Default)]
@@ -229,6 +795,25 @@ impl GameEvents {
         self.has_events = true;
     }
 
+    pub fn contains(&self, ev: &GameEvent) -> bool {
+        self.has_events && self.storage.contains(ev)
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, GameEvent> {
+        self.storage.iter()
+    }
+
+    // how many events were emitted so far, `truncate` goes back to that
+    pub fn emitted(&self) -> usize {
+        self.storage.len()
+    }
+
+    // drops the events emitted after the first `emitted`
+    pub fn truncate(&mut self, emitted: usize) {
+        self.storage.truncate(emitted);
+        self.has_events = !self.storage.is_empty();
+    }
+
     pub fn emit_on_slider_change(&mut self, slider_change: SliderStateChange) {
         match slider_change {
             SliderStateChange::NoChange => {
@@ -356,4 +941,4 @@ fn make_surface(
         None,
         None,
     ).unwrap()
-}
\ No newline at end of file
+}
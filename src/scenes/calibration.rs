@@ -0,0 +1,268 @@
+use std::{cell::RefCell, rc::Rc};
+use std::time::Instant;
+use glutin::event::VirtualKeyCode;
+use glutin::window::Window;
+use glutin::{ContextWrapper, PossiblyCurrent};
+use kira::instance::InstanceSettings;
+use kira::manager::{AudioManager, AudioManagerSettings};
+use kira::sound::SoundSettings;
+use kira::sound::handle::SoundHandle;
+use skia_safe::*;
+use skia_safe::font_style::{Weight, Width, Slant};
+use skia_safe::gpu::Context as GpuContext;
+use specs::{DispatcherBuilder, Read, System, World, WorldExt, Write};
+use splines::{Spline, Interpolation, Key};
+use crate::consts::{CALIBRATION_BEAT_INTERVAL, CALIBRATION_MIN_TAPS};
+use crate::game_thread::{DispatcherScene, NextScene, SceneChange, SceneContext};
+use crate::scenes::insert_scene_resources;
+use crate::resources::{GameCursor, GameEvents, GameInputKeyBinding, GameInputState, Graphics, Time};
+use crate::settings::Settings;
+use crate::systems::InputSystem;
+
+#[derive(Debug)]
+pub struct OffsetCalibration {
+    pub first_beat_at: f64,
+    pub next_beat_at: f64,
+    pub last_beat_at: Option<Instant>,
+    // signed distance of each tap to the closest beat, in seconds
+    pub tap_errors: Vec<f64>,
+}
+
+impl Default for OffsetCalibration {
+    fn default() -> Self {
+        OffsetCalibration {
+            first_beat_at: 1.0,
+            next_beat_at: 1.0,
+            last_beat_at: None,
+            tap_errors: Vec::new(),
+        }
+    }
+}
+
+impl OffsetCalibration {
+    pub fn record_tap(&mut self, tapped_at: f64) {
+        if tapped_at < self.first_beat_at - CALIBRATION_BEAT_INTERVAL / 2.0 {
+            return;
+        }
+        self.tap_errors.push(tap_error(tapped_at, self.first_beat_at, CALIBRATION_BEAT_INTERVAL));
+    }
+
+    // average tap error, positive when the user hears the beat late
+    pub fn suggested_offset_ms(&self) -> Option<i32> {
+        if self.tap_errors.len() < CALIBRATION_MIN_TAPS {
+            return None;
+        }
+        let average = self.tap_errors.iter().sum::<f64>() / self.tap_errors.len() as f64;
+        Some((average * 1000.0).round() as i32)
+    }
+}
+
+fn tap_error(tapped_at: f64, first_beat_at: f64, beat_interval: f64) -> f64 {
+    let closest_beat = ((tapped_at - first_beat_at) / beat_interval).round();
+    tapped_at - (first_beat_at + closest_beat * beat_interval)
+}
+
+#[test]
+fn tap_error_test() {
+    assert!((tap_error(1.52, 1.0, 0.5) - 0.02).abs() < 1e-9);
+    assert!((tap_error(1.98, 1.0, 0.5) + 0.02).abs() < 1e-9);
+}
+
+// plays the metronome and collects the user's taps
+pub struct CalibrationSystem {
+    _manager: AudioManager,
+    click: SoundHandle,
+}
+
+impl CalibrationSystem {
+    pub fn new() -> Self {
+        let mut manager = AudioManager::new(AudioManagerSettings::default())
+            .unwrap();
+        let click = manager.load_sound("assets/soft-hitnormal.wav", SoundSettings::default())
+            .expect("Failed to load metronome sound");
+        Self {
+            _manager: manager,
+            click,
+        }
+    }
+}
+
+impl<'a> System<'a> for CalibrationSystem {
+    type SystemData = (
+        Read<'a, Time>,
+        Read<'a, GameInputState>,
+        Write<'a, OffsetCalibration>,
+        Write<'a, Settings>,
+        Write<'a, SceneChange>,
+    );
+
+    fn run(&mut self, (
+        time,
+        input_state,
+        mut calibration,
+        mut settings,
+        mut scene_change,
+    ): Self::SystemData) {
+        if time.secs_since_start >= calibration.next_beat_at {
+            self.click.play(InstanceSettings::default())
+                .unwrap();
+            calibration.last_beat_at = Some(time.now);
+            calibration.next_beat_at += CALIBRATION_BEAT_INTERVAL;
+        }
+
+        if input_state.is_key_active(&GameInputKeyBinding::Hit1) || input_state.is_key_active(&GameInputKeyBinding::Hit2) {
+            calibration.record_tap(time.secs_since_start);
+        }
+
+        if input_state.active_set.contains(&VirtualKeyCode::R) {
+            calibration.tap_errors.clear();
+        }
+
+        if input_state.active_set.contains(&VirtualKeyCode::Return) {
+            if let Some(offset) = calibration.suggested_offset_ms() {
+                log::info!("Saving universal offset {} ms", offset);
+                settings.universal_offset_ms = offset;
+                settings.save();
+                scene_change.request(NextScene::Settings);
+            }
+        }
+
+        if input_state.active_set.contains(&VirtualKeyCode::Escape) {
+            scene_change.request(NextScene::Settings);
+        }
+    }
+}
+
+pub struct CalibrationRenderingSystem {
+    graphics: Graphics,
+    gpu_context: Rc<RefCell<GpuContext>>,
+    window_ctx: Rc<ContextWrapper<PossiblyCurrent, Window>>,
+    font: Font,
+    font_paint: Paint,
+    beat_paint: Paint,
+    beat_flash_spline: Spline<f32, f32>,
+}
+
+impl CalibrationRenderingSystem {
+    pub fn new(
+        window_ctx: Rc<ContextWrapper<PossiblyCurrent, Window>>,
+        gpu_context: Rc<RefCell<GpuContext>>,
+    ) -> Self {
+        let font = {
+            let typeface = Typeface::new("Verdana", FontStyle::new(Weight::NORMAL, Width::NORMAL, Slant::Upright))
+                .unwrap();
+            Font::new(typeface, 18.0)
+        };
+
+        let font_paint = {
+            let mut builder = Paint::default();
+            builder.set_anti_alias(true);
+            builder.set_color(Color::from_rgb(211, 211, 211));
+            builder.set_style(PaintStyle::Fill);
+            builder
+        };
+
+        let beat_paint = {
+            let mut builder = Paint::default();
+            builder.set_anti_alias(true);
+            builder.set_color(Color::from_rgb(252, 163, 17));
+            builder.set_style(PaintStyle::Fill);
+            builder
+        };
+
+        let beat_flash_spline = {
+            let start = Key::new(0.0, 1.0, Interpolation::Linear);
+            let end = Key::new(0.25, 0.0, Interpolation::Linear);
+            Spline::from_vec(vec![start, end])
+        };
+
+        let graphics = Graphics::new(&window_ctx.clone(), &mut gpu_context.clone().borrow_mut());
+
+        Self {
+            graphics,
+            gpu_context,
+            window_ctx,
+            font,
+            font_paint,
+            beat_paint,
+            beat_flash_spline,
+        }
+    }
+
+    fn draw_text(&self, canvas: &mut Canvas, text: String, pos: Point) {
+        canvas.draw_text_blob(
+            TextBlob::from_str(text.as_str(), &self.font)
+                .expect("Couldn't draw text"),
+            pos,
+            &self.font_paint,
+        );
+    }
+}
+
+impl<'a> System<'a> for CalibrationRenderingSystem {
+    type SystemData = (
+        Read<'a, Time>,
+        Read<'a, GameEvents>,
+        Read<'a, OffsetCalibration>,
+        Read<'a, Settings>,
+    );
+
+    fn run(&mut self, (time, events, calibration, settings): Self::SystemData) {
+        events.on_resized(|_| {
+            self.graphics = Graphics::new(&self.window_ctx.clone(), &mut self.gpu_context.clone().borrow_mut());
+        });
+
+        let mut surface = self.graphics.surface.clone();
+        let canvas = surface.canvas();
+        canvas.clear(Color::from_rgb(24, 24, 24));
+
+        let center_x = self.graphics.width_f32 / 2.0;
+        let left = center_x - 200.0;
+        let top = self.graphics.height_f32 / 2.0 - 120.0;
+
+        let flash = calibration.last_beat_at
+            .map(|at| time.now.duration_since(at).as_secs_f32())
+            .and_then(|elapsed| self.beat_flash_spline.clamped_sample(elapsed))
+            .unwrap_or(0.0);
+        let mut beat_paint = self.beat_paint.clone();
+        beat_paint.set_alpha_f(0.2 + 0.8 * flash);
+        canvas.draw_circle((center_x, top), 30.0, &beat_paint);
+
+        let suggestion = match calibration.suggested_offset_ms() {
+            Some(offset) => format!("Suggested offset: {} ms", offset),
+            None => format!("Keep tapping... ({}/{})", calibration.tap_errors.len(), CALIBRATION_MIN_TAPS),
+        };
+
+        let lines = vec![
+            "Tap G or H along with the metronome".to_string(),
+            format!("Taps: {}", calibration.tap_errors.len()),
+            suggestion,
+            format!("Current offset: {} ms", settings.universal_offset_ms),
+            "Enter - save, R - reset, Escape - cancel".to_string(),
+        ];
+
+        for (i, line) in lines.into_iter().enumerate() {
+            self.draw_text(canvas, line, Point::new(left, top + 80.0 + i as f32 * 30.0));
+        }
+
+        canvas.flush();
+    }
+}
+
+pub fn make_calibration_scene(ctx: &SceneContext) -> DispatcherScene {
+    let mut world = World::new();
+
+    insert_scene_resources(&mut world);
+    world.insert(GameCursor::default());
+    world.insert(GameInputState::default());
+    world.insert(OffsetCalibration::default());
+    world.insert(ctx.session.settings.clone());
+
+    let dispatcher = DispatcherBuilder::new()
+        .with(InputSystem::default(), "input-system", &[])
+        .with_thread_local(CalibrationSystem::new())
+        .with_thread_local(CalibrationRenderingSystem::new(ctx.window_ctx.clone(), ctx.gpu_context.clone()))
+        .build();
+
+    DispatcherScene::new(world, dispatcher)
+}
@@ -0,0 +1,133 @@
+use specs::{Dispatcher, DispatcherBuilder, RunNow, World, WorldExt};
+use crate::components::*;
+use crate::difficulty::PlayDifficulty;
+use crate::game_thread::{GameConfig, Scene, SceneContext};
+use crate::mods::Mod;
+use crate::rendering_system::RenderingSystem;
+use crate::resources::*;
+use crate::scenes::insert_scene_resources;
+use crate::scoring::make_scoring_model;
+use crate::systems::*;
+
+// renders once per frame, however many frames of gameplay a replay runs to catch up
+pub struct GameplayScene {
+    world: World,
+    dispatcher: Dispatcher<'static, 'static>,
+    rendering: RenderingSystem,
+}
+
+impl Scene for GameplayScene {
+    fn world(&mut self) -> &mut World {
+        &mut self.world
+    }
+
+    fn setup(&mut self) {
+        self.dispatcher.setup(&mut self.world);
+        self.rendering.setup(&mut self.world);
+    }
+
+    fn run_frame(&mut self) {
+        run_gameplay_frame(&mut self.world, &mut self.dispatcher);
+        self.rendering.run_now(&self.world);
+    }
+}
+
+pub fn make_gameplay_scene(game_config: GameConfig, ctx: &SceneContext) -> GameplayScene {
+    let mut world = World::new();
+
+    let mods = game_config.mods;
+    let unsupported = mods.iter()
+        .filter(|m| !m.is_supported())
+        .map(|m| m.acronym())
+        .collect::<String>();
+    if !unsupported.is_empty() {
+        log::warn!("Mods {} are not supported yet, ignoring them", unsupported);
+    }
+
+    let mut loaded_beatmap = game_config.beatmap.clone();
+    game_config.difficulty_adjust.apply(&mut loaded_beatmap.beatmap);
+    let rate = game_config.difficulty_adjust.rate(mods);
+    // nightcore raises the pitch along with the speed, everything else keeps it
+    let stretch = if mods.contains(Mod::Nightcore) { 1.0 } else { rate };
+    let nightcore_beat = if mods.contains(Mod::Nightcore) {
+        Some(NightcoreBeat::new(&loaded_beatmap.beatmap.timing_points))
+    } else {
+        None
+    };
+    let difficulty = PlayDifficulty::new(&loaded_beatmap.beatmap, mods);
+    if mods.contains(Mod::HardRock) {
+        loaded_beatmap.beatmap.flip_vertically();
+    }
+
+    // components
+    world.register::<GamePos>();
+    world.register::<Circle>();
+    world.register::<Slider>();
+    world.register::<Spinner>();
+    world.register::<Lifetime>();
+    world.register::<CircleHitRating>();
+    world.register::<DespawnObject>();
+    world.register::<HitSound>();
+    world.register::<JudgementPopup>();
+    world.register::<Shake>();
+
+    // resources
+    insert_scene_resources(&mut world);
+    world.insert(GameCursor {
+        window_x: 0.0,
+        window_y: 0.0,
+    });
+    // the offset is wall clock time, gameplay time runs at the song rate
+    world.insert(Time::starting_song_at(
+        loaded_beatmap.beatmap.audio_lead_in / 1000.0 - game_config.audio_offset * rate
+    ).with_rate(rate));
+    world.insert(Trail::default());
+    world.insert(TrailTimer::default());
+    world.insert(Hp::new(difficulty.miss_drain(), difficulty.lives));
+    world.insert(GameArea::default());
+    world.insert(GameInputState::default());
+    world.insert(Combo::default());
+    world.insert(Score::new(
+        game_config.scoring_mode,
+        make_scoring_model(game_config.scoring_mode, &loaded_beatmap.beatmap, game_config.mods),
+    ));
+    world.insert(PauseMenu::default());
+    world.insert(MapProgress::new(loaded_beatmap.beatmap.object_time_range_in_secs()));
+    world.insert(PlayStats::default());
+    world.insert(HitErrorMeter::default());
+    world.insert(ReplayRecorder::default());
+    let watching_replay = game_config.replay.is_some();
+    if let Some(replay) = &game_config.replay {
+        world.insert(ReplayPlayback::new(replay, rate));
+    }
+    world.insert(mods);
+    world.insert(difficulty);
+    world.insert(game_config);
+
+    let mut dispatcher = DispatcherBuilder::new();
+    if watching_replay {
+        dispatcher.add(ReplayPlaybackSystem, "replay-playback-system", &[]);
+    }
+    let dispatcher = dispatcher
+        .with(PauseSystem, "pause-system", &[])
+        .with(ReplayGated(InputSystem::new(mods, &difficulty, watching_replay)), "input-system", &["pause-system"])
+        .with(TrailSystem, "trail-system", &["input-system"])
+        .with(ReplayGated(ObjectSpawnerSystem::new(loaded_beatmap.beatmap, loaded_beatmap.audio_path)), "object-spawner-system", &["pause-system"])
+        .with(ReplayGated(HitSystem), "hit-system", &["object-spawner-system", "input-system"])
+        .with(ReplayGated(CircleLifetimeSystem), "circle-lifetime-system", &["hit-system"])
+        .with(ReplayGated(SliderLifetimeSystem), "slider-lifetime-system", &["hit-system"])
+        .with(ReplayGated(SpinnerSystem), "spinner-system", &["object-spawner-system", "input-system"])
+        .with(ReplayGated(LifetimeSystem), "lifetime-system" , &["circle-lifetime-system", "slider-lifetime-system", "spinner-system"])
+        .with(ReplayGated(ScoringSystem), "scoring-system", &["lifetime-system"])
+        .with(MapEndSystem, "map-end-system", &["scoring-system"])
+        .with(JudgementPopupSystem, "judgement-popup-system", &["scoring-system"])
+        .with_thread_local(AudioSystem::new(&loaded_beatmap.samples, rate, stretch, nightcore_beat))
+        .build();
+    let rendering = RenderingSystem::new(ctx.window_ctx.clone(), ctx.gpu_context.clone(), loaded_beatmap.background_path.as_deref(), difficulty);
+
+    GameplayScene {
+        world,
+        dispatcher,
+        rendering,
+    }
+}
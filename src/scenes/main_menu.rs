@@ -0,0 +1,37 @@
+use specs::{Read, System, World, Write};
+use crate::game_thread::{DispatcherScene, NextScene, SceneChange, SceneContext};
+use crate::menu::{make_menu_scene, Menu, MenuAction, MenuActions};
+
+const PLAY: usize = 0;
+const SETTINGS: usize = 1;
+const QUIT: usize = 2;
+
+pub struct MainMenuSystem;
+
+impl<'a> System<'a> for MainMenuSystem {
+    type SystemData = (
+        Read<'a, MenuActions>,
+        Write<'a, SceneChange>,
+    );
+
+    fn run(&mut self, (actions, mut scene_change): Self::SystemData) {
+        for action in actions.0.iter() {
+            match action {
+                MenuAction::Select(PLAY) => scene_change.request(NextScene::SongSelect),
+                MenuAction::Select(SETTINGS) => scene_change.request(NextScene::Settings),
+                MenuAction::Select(QUIT) | MenuAction::Back => scene_change.request(NextScene::Quit),
+                _ => {}
+            }
+        }
+    }
+}
+
+pub fn make_main_menu_scene(ctx: &SceneContext) -> DispatcherScene {
+    let menu = Menu::new("rusty-osu", vec![
+        "Play".to_string(),
+        "Settings".to_string(),
+        "Quit".to_string(),
+    ]);
+
+    make_menu_scene(World::new(), menu, MainMenuSystem, ctx)
+}
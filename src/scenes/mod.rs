@@ -0,0 +1,19 @@
+use specs::{World, WorldExt};
+use crate::game_thread::{EventLoopMsg, SceneChange};
+use crate::resources::{GameEvents, SongClock, Time};
+
+pub mod calibration;
+pub mod gameplay;
+pub mod main_menu;
+pub mod results;
+pub mod settings_menu;
+pub mod song_select;
+
+// resources the game thread expects every scene to have
+pub fn insert_scene_resources(world: &mut World) {
+    world.insert(Time::default());
+    world.insert(SongClock::default());
+    world.insert(Vec::<EventLoopMsg>::with_capacity(8));
+    world.insert(GameEvents::default());
+    world.insert(SceneChange::default());
+}
@@ -0,0 +1,127 @@
+use skia_safe::{Canvas, Color, Paint, PaintStyle, Rect};
+use specs::{Read, System, World, Write};
+use crate::game_thread::{DispatcherScene, GameConfig, NextScene, SceneChange, SceneContext};
+use crate::menu::{make_menu_scene_with_rendering, Menu, MenuAction, MenuActions, MenuRenderingSystem};
+use crate::resources::{GameEvents, Graphics, PlayResult};
+
+const RETRY: usize = 0;
+const BACK: usize = 1;
+
+const HISTOGRAM_BINS: usize = 40;
+
+pub struct ResultsSystem {
+    game_config: GameConfig,
+}
+
+impl<'a> System<'a> for ResultsSystem {
+    type SystemData = (
+        Read<'a, MenuActions>,
+        Write<'a, SceneChange>,
+    );
+
+    fn run(&mut self, (actions, mut scene_change): Self::SystemData) {
+        for action in actions.0.iter() {
+            match action {
+                MenuAction::Select(RETRY) => scene_change.request(NextScene::Gameplay(self.game_config.clone())),
+                MenuAction::Select(BACK) | MenuAction::Back => scene_change.request(NextScene::SongSelect),
+                _ => {}
+            }
+        }
+    }
+}
+
+// the results menu with a hit error histogram next to it
+pub struct ResultsRenderingSystem {
+    menu_rendering: MenuRenderingSystem,
+    histogram: Vec<u32>,
+    bar_paint: Paint,
+    center_paint: Paint,
+}
+
+impl<'a> System<'a> for ResultsRenderingSystem {
+    type SystemData = (
+        Read<'a, GameEvents>,
+        Read<'a, Menu>,
+    );
+
+    fn run(&mut self, (events, menu): Self::SystemData) {
+        let histogram = &self.histogram;
+        let bar_paint = &self.bar_paint;
+        let center_paint = &self.center_paint;
+        self.menu_rendering.draw(&events, &menu, |canvas, graphics| {
+            draw_histogram(canvas, graphics, histogram, bar_paint, center_paint);
+        });
+    }
+}
+
+fn draw_histogram(canvas: &mut Canvas, graphics: &Graphics, histogram: &[u32], bar_paint: &Paint, center_paint: &Paint) {
+    let left = graphics.width_f32 * 0.55;
+    let right = graphics.width_f32 - 60.0;
+    let top = 120.0;
+    let bottom = 320.0;
+
+    let highest = histogram.iter().cloned().max().unwrap_or(0).max(1) as f32;
+    let bar_width = (right - left) / histogram.len() as f32;
+
+    for (i, count) in histogram.iter().enumerate() {
+        let bar_left = left + bar_width * i as f32;
+        let bar_height = (bottom - top) * *count as f32 / highest;
+        canvas.draw_rect(
+            Rect::new(bar_left + 1.0, bottom - bar_height, bar_left + bar_width - 1.0, bottom),
+            bar_paint,
+        );
+    }
+
+    let center = (left + right) / 2.0;
+    canvas.draw_line((center, top - 10.0), (center, bottom + 10.0), center_paint);
+    canvas.draw_line((left, bottom), (right, bottom), center_paint);
+}
+
+pub fn make_results_scene(result: PlayResult, game_config: GameConfig, ctx: &SceneContext) -> DispatcherScene {
+    let stats = &result.stats;
+
+    let mut menu = Menu::new(if result.failed { "Failed" } else { "Results" }, vec![
+        "Retry".to_string(),
+        "Back to song select".to_string(),
+    ]);
+    menu.info.push(result.beatmap_label.clone());
+    let adjust = &game_config.difficulty_adjust;
+    let mods = if adjust.is_empty() { result.mods.to_string() } else { format!("{} {}", result.mods, adjust) };
+    menu.info.push(if result.mods.is_ranked() && adjust.is_empty() {
+        format!("Mods: {}", mods)
+    } else {
+        format!("Mods: {} (unranked)", mods)
+    });
+    menu.info.push(format!("Grade: {}", stats.grade().label()));
+    menu.info.push(format!("Score: {} ({})", result.score, result.scoring_mode.label()));
+    menu.info.push(format!("Accuracy: {:.2}%", stats.accuracy() * 100.0));
+    menu.info.push(format!("Max combo: {}x", stats.max_combo));
+    menu.info.push(format!("300: {}  100: {}  50: {}  Miss: {}", stats.great, stats.good, stats.meh, stats.miss));
+    menu.info.push(format!("Slider breaks: {}", stats.slider_breaks));
+    menu.info.push(match stats.unstable_rate() {
+        Some(unstable_rate) => format!("Unstable rate: {:.2}", unstable_rate),
+        None => "Unstable rate: -".to_string(),
+    });
+
+    let rendering_system = ResultsRenderingSystem {
+        menu_rendering: MenuRenderingSystem::new(ctx.window_ctx.clone(), ctx.gpu_context.clone()),
+        histogram: stats.hit_error_histogram(result.hit_windows.meh, HISTOGRAM_BINS),
+        bar_paint: {
+            let mut builder = Paint::default();
+            builder.set_anti_alias(true);
+            builder.set_color(Color::from_rgb(252, 163, 17));
+            builder.set_style(PaintStyle::Fill);
+            builder
+        },
+        center_paint: {
+            let mut builder = Paint::default();
+            builder.set_anti_alias(true);
+            builder.set_color(Color::from_rgb(211, 211, 211));
+            builder.set_stroke_width(2.0);
+            builder.set_style(PaintStyle::Stroke);
+            builder
+        },
+    };
+
+    make_menu_scene_with_rendering(World::new(), menu, ResultsSystem { game_config }, rendering_system, ctx)
+}
@@ -0,0 +1,57 @@
+use specs::{Read, System, World, Write};
+use crate::game_thread::{DispatcherScene, NextScene, SceneChange, SceneContext};
+use crate::menu::{make_menu_scene, Menu, MenuAction, MenuActions};
+use crate::settings::Settings;
+
+const UNIVERSAL_OFFSET: usize = 0;
+const CALIBRATE: usize = 1;
+const BACK: usize = 2;
+
+// milliseconds per left/right press
+const OFFSET_STEP: i32 = 5;
+
+pub struct SettingsMenuSystem;
+
+impl<'a> System<'a> for SettingsMenuSystem {
+    type SystemData = (
+        Read<'a, MenuActions>,
+        Write<'a, Menu>,
+        Write<'a, Settings>,
+        Write<'a, SceneChange>,
+    );
+
+    fn run(&mut self, (actions, mut menu, mut settings, mut scene_change): Self::SystemData) {
+        for action in actions.0.iter() {
+            match action {
+                MenuAction::Left(UNIVERSAL_OFFSET) => {
+                    settings.universal_offset_ms -= OFFSET_STEP;
+                    settings.save();
+                }
+                MenuAction::Right(UNIVERSAL_OFFSET) => {
+                    settings.universal_offset_ms += OFFSET_STEP;
+                    settings.save();
+                }
+                MenuAction::Select(CALIBRATE) => scene_change.request(NextScene::OffsetCalibration),
+                MenuAction::Select(BACK) | MenuAction::Back => scene_change.request(NextScene::MainMenu),
+                _ => {}
+            }
+        }
+
+        menu.items[UNIVERSAL_OFFSET] = universal_offset_label(&settings);
+    }
+}
+
+fn universal_offset_label(settings: &Settings) -> String {
+    format!("Universal offset: < {} ms >", settings.universal_offset_ms)
+}
+
+pub fn make_settings_scene(ctx: &SceneContext) -> DispatcherScene {
+    let mut menu = Menu::new("Settings", vec![
+        universal_offset_label(&ctx.session.settings),
+        "Calibrate offset".to_string(),
+        "Back".to_string(),
+    ]);
+    menu.info.push("Left/Right changes the selected value".to_string());
+
+    make_menu_scene(World::new(), menu, SettingsMenuSystem, ctx)
+}
@@ -0,0 +1,147 @@
+use std::path::{Path, PathBuf};
+use specs::{Read, ReadExpect, System, World, WorldExt, Write};
+use crate::beatmap_loader::{BeatmapSet, LoadedBeatmap};
+use crate::game_thread::{DispatcherScene, GameConfig, NextScene, SceneChange, SceneContext};
+use crate::menu::{make_menu_scene, Menu, MenuAction, MenuActions};
+use crate::difficulty::DifficultyAdjust;
+use crate::mods::Mods;
+use crate::scoring::ScoringMode;
+use crate::settings::Settings;
+
+// a single playable difficulty, `set_path` is `None` for the bundled map
+#[derive(Debug, Clone)]
+pub struct SongEntry {
+    pub set_path: Option<PathBuf>,
+    pub difficulty: String,
+    pub label: String,
+}
+
+impl SongEntry {
+    fn load(&self) -> Option<LoadedBeatmap> {
+        let set_path = match &self.set_path {
+            Some(set_path) => set_path,
+            None => return Some(LoadedBeatmap::bundled()),
+        };
+        let loaded = BeatmapSet::open(set_path)
+            .and_then(|set| set.load(Some(&self.difficulty)));
+        match loaded {
+            Ok(loaded) => Some(loaded),
+            Err(err) => {
+                log::error!("Failed to load {}: {}", self.label, err);
+                None
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SongEntries(pub Vec<SongEntry>);
+
+pub struct SongSelectSystem {
+    mods: Mods,
+    difficulty_adjust: DifficultyAdjust,
+    scoring_mode: ScoringMode,
+    offset_override_ms: Option<i32>,
+}
+
+impl<'a> System<'a> for SongSelectSystem {
+    type SystemData = (
+        Read<'a, MenuActions>,
+        Read<'a, SongEntries>,
+        ReadExpect<'a, Settings>,
+        Write<'a, SceneChange>,
+    );
+
+    fn run(&mut self, (actions, entries, settings, mut scene_change): Self::SystemData) {
+        for action in actions.0.iter() {
+            match action {
+                MenuAction::Select(index) => {
+                    if let Some(beatmap) = entries.0.get(*index).and_then(SongEntry::load) {
+                        scene_change.request(NextScene::Gameplay(GameConfig {
+                            audio_offset: settings.audio_offset(&beatmap.hash, self.offset_override_ms),
+                            beatmap,
+                            mods: self.mods,
+                            difficulty_adjust: self.difficulty_adjust,
+                            scoring_mode: self.scoring_mode,
+                            replay: None,
+                        }));
+                    }
+                }
+                MenuAction::Back => scene_change.request(NextScene::MainMenu),
+                _ => {}
+            }
+        }
+    }
+}
+
+pub fn make_song_select_scene(ctx: &SceneContext) -> DispatcherScene {
+    let entries = find_song_entries(&ctx.session.songs_folder, ctx.session.extra_beatmap_set.as_deref());
+
+    let mut menu = Menu::new("Song select", entries.iter().map(|it| it.label.clone()).collect());
+    menu.info.push(format!("Mods: {}, scoring: {}", ctx.session.mods, ctx.session.scoring_mode.label()));
+    if !ctx.session.difficulty_adjust.is_empty() {
+        menu.info.push(format!("Difficulty adjust: {}", ctx.session.difficulty_adjust));
+    }
+    menu.info.push(format!("Beatmaps are read from {}", ctx.session.songs_folder.display()));
+
+    let mut world = World::new();
+    world.insert(SongEntries(entries));
+
+    make_menu_scene(world, menu, SongSelectSystem {
+        mods: ctx.session.mods,
+        difficulty_adjust: ctx.session.difficulty_adjust,
+        scoring_mode: ctx.session.scoring_mode,
+        offset_override_ms: ctx.session.offset_override_ms,
+    }, ctx)
+}
+
+fn find_song_entries(songs_folder: &Path, extra_beatmap_set: Option<&Path>) -> Vec<SongEntry> {
+    let mut entries = vec![bundled_song_entry()];
+
+    let mut set_paths = Vec::new();
+    if let Some(path) = extra_beatmap_set {
+        set_paths.push(path.to_path_buf());
+    }
+    match std::fs::read_dir(songs_folder) {
+        Ok(dir) => {
+            let mut paths: Vec<PathBuf> = dir
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .collect();
+            paths.sort();
+            set_paths.extend(paths);
+        }
+        Err(err) => log::info!("No beatmaps in {}: {}", songs_folder.display(), err),
+    }
+
+    for set_path in set_paths {
+        let set = match BeatmapSet::open(&set_path) {
+            Ok(set) => set,
+            Err(err) => {
+                log::debug!("Skipping {}: {}", set_path.display(), err);
+                continue;
+            }
+        };
+        let set_name = set_path.file_stem()
+            .map(|it| it.to_string_lossy().to_string())
+            .unwrap_or_default();
+        for difficulty in set.difficulties() {
+            entries.push(SongEntry {
+                set_path: Some(set_path.clone()),
+                difficulty: difficulty.version.clone(),
+                label: format!("{} [{}]", set_name, difficulty.version),
+            });
+        }
+    }
+
+    entries
+}
+
+fn bundled_song_entry() -> SongEntry {
+    let bundled = LoadedBeatmap::bundled();
+    SongEntry {
+        set_path: None,
+        difficulty: bundled.beatmap.version.clone(),
+        label: bundled.label(),
+    }
+}
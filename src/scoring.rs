@@ -0,0 +1,230 @@
+use crate::mods::Mods;
+use crate::osu_parser::OsuBeatMap;
+
+// osu! ScoreV1, see https://osu.ppy.sh/wiki/en/Gameplay/Score/ScoreV1
+pub const SLIDER_HEAD_SCORE: u64 = 30;
+pub const SLIDER_TICK_SCORE: u64 = 10;
+pub const SLIDER_REPEAT_SCORE: u64 = 30;
+pub const SLIDER_END_SCORE: u64 = 30;
+pub const SPINNER_SPIN_SCORE: u64 = 100;
+pub const SPINNER_BONUS_SPIN_SCORE: u64 = 1000;
+
+// ScoreV2 splits this between combo and accuracy
+const SCORE_V2_MAX: f64 = 1_000_000.0;
+const SCORE_V2_COMBO_PORTION: f64 = 0.7;
+const SCORE_V2_ACCURACY_PORTION: f64 = 0.3;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ScoringMode {
+    V1,
+    V2,
+}
+
+impl ScoringMode {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ScoringMode::V1 => "ScoreV1",
+            ScoringMode::V2 => "ScoreV2",
+        }
+    }
+}
+
+// points given outside of the regular judgements
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ScoreBonus {
+    SliderHead,
+    SliderTick,
+    SliderRepeat,
+    SliderEnd,
+    SpinnerSpin,
+    SpinnerBonusSpin,
+}
+
+pub trait ScoringModel {
+    // `combo` is the combo before this hit, misses have a hit value of 0
+    fn on_hit(&mut self, hit_value: u64, combo: u64);
+    fn on_bonus(&mut self, bonus: ScoreBonus);
+    fn value(&self) -> u64;
+    fn reset(&mut self);
+}
+
+pub fn make_scoring_model(mode: ScoringMode, beatmap: &OsuBeatMap, mods: Mods) -> Box<dyn ScoringModel + Send + Sync> {
+    match mode {
+        ScoringMode::V1 => Box::new(ScoreV1::new(difficulty_multiplier(beatmap), mods.score_multiplier(mode))),
+        ScoringMode::V2 => Box::new(ScoreV2::new(beatmap.hit_objects.len(), mods.score_multiplier(mode))),
+    }
+}
+
+pub struct ScoreV1 {
+    value: u64,
+    difficulty_multiplier: f64,
+    mod_multiplier: f64,
+}
+
+impl ScoreV1 {
+    pub fn new(difficulty_multiplier: f64, mod_multiplier: f64) -> Self {
+        Self {
+            value: 0,
+            difficulty_multiplier,
+            mod_multiplier,
+        }
+    }
+}
+
+impl ScoringModel for ScoreV1 {
+    fn on_hit(&mut self, hit_value: u64, combo: u64) {
+        self.value += hit_score(hit_value, combo, self.difficulty_multiplier, self.mod_multiplier);
+    }
+
+    fn on_bonus(&mut self, bonus: ScoreBonus) {
+        self.value += match bonus {
+            ScoreBonus::SliderHead => SLIDER_HEAD_SCORE,
+            ScoreBonus::SliderTick => SLIDER_TICK_SCORE,
+            ScoreBonus::SliderRepeat => SLIDER_REPEAT_SCORE,
+            ScoreBonus::SliderEnd => SLIDER_END_SCORE,
+            ScoreBonus::SpinnerSpin => SPINNER_SPIN_SCORE,
+            ScoreBonus::SpinnerBonusSpin => SPINNER_BONUS_SPIN_SCORE,
+        };
+    }
+
+    fn value(&self) -> u64 {
+        self.value
+    }
+
+    fn reset(&mut self) {
+        self.value = 0;
+    }
+}
+
+// 700k for combo, 300k for accuracy, spinner bonus on top
+pub struct ScoreV2 {
+    object_count: usize,
+    mod_multiplier: f64,
+    combo_portion: f64,
+    max_combo_portion: f64,
+    // sum of hit values and how many objects got judged so far
+    hit_points: u64,
+    judged: usize,
+    bonus: u64,
+}
+
+impl ScoreV2 {
+    pub fn new(object_count: usize, mod_multiplier: f64) -> Self {
+        // every object hit for a 300 without a break
+        let max_combo_portion = (0..object_count)
+            .map(|combo| combo_portion(300, combo as u64))
+            .sum();
+        Self {
+            object_count,
+            mod_multiplier,
+            combo_portion: 0.0,
+            max_combo_portion,
+            hit_points: 0,
+            judged: 0,
+            bonus: 0,
+        }
+    }
+}
+
+fn combo_portion(hit_value: u64, combo: u64) -> f64 {
+    hit_value as f64 * (1.0 + combo as f64 / 10.0)
+}
+
+impl ScoringModel for ScoreV2 {
+    fn on_hit(&mut self, hit_value: u64, combo: u64) {
+        self.combo_portion += combo_portion(hit_value, combo);
+        self.hit_points += hit_value;
+        self.judged += 1;
+    }
+
+    fn on_bonus(&mut self, bonus: ScoreBonus) {
+        if bonus == ScoreBonus::SpinnerBonusSpin {
+            self.bonus += SPINNER_BONUS_SPIN_SCORE;
+        }
+    }
+
+    fn value(&self) -> u64 {
+        if self.judged == 0 || self.object_count == 0 {
+            return self.bonus;
+        }
+        // slider ticks add combo as well, so this can overshoot a little
+        let combo = (self.combo_portion / self.max_combo_portion).min(1.0);
+        let accuracy = self.hit_points as f64 / (self.judged * 300) as f64;
+        let judged = (self.judged as f64 / self.object_count as f64).min(1.0);
+        let score = SCORE_V2_MAX * (SCORE_V2_COMBO_PORTION * combo + SCORE_V2_ACCURACY_PORTION * accuracy.powi(10) * judged);
+        (score * self.mod_multiplier).round() as u64 + self.bonus
+    }
+
+    fn reset(&mut self) {
+        self.combo_portion = 0.0;
+        self.hit_points = 0;
+        self.judged = 0;
+        self.bonus = 0;
+    }
+}
+
+// score for a single judgement, `combo` is the combo before this hit
+pub fn hit_score(hit_value: u64, combo: u64, difficulty_multiplier: f64, mod_multiplier: f64) -> u64 {
+    let combo_multiplier = combo.saturating_sub(1) as f64;
+    let bonus = hit_value as f64 * combo_multiplier * difficulty_multiplier * mod_multiplier / 25.0;
+    hit_value + bonus as u64
+}
+
+pub fn difficulty_multiplier(beatmap: &OsuBeatMap) -> f64 {
+    difficulty_multiplier_from(
+        beatmap.hp_drain_rate,
+        beatmap.circle_size,
+        beatmap.overall_difficulty,
+        beatmap.hit_objects.len(),
+        drain_time_in_secs(beatmap),
+    )
+}
+
+// breaks are not taken out
+fn drain_time_in_secs(beatmap: &OsuBeatMap) -> f64 {
+    let (first, last) = beatmap.object_time_range_in_secs();
+    last - first
+}
+
+fn difficulty_multiplier_from(hp: f64, cs: f64, od: f64, object_count: usize, drain_time_in_secs: f64) -> f64 {
+    let object_density = (object_count as f64 / drain_time_in_secs.max(1.0) * 8.0)
+        .max(0.0)
+        .min(16.0);
+    ((hp + cs + od + object_density) / 38.0 * 5.0).round()
+}
+
+#[test]
+fn hit_score_test() {
+    assert_eq!(hit_score(300, 0, 4.0, 1.0), 300);
+    assert_eq!(hit_score(300, 1, 4.0, 1.0), 300);
+    // 300 + 300 * 10 * 4 / 25
+    assert_eq!(hit_score(300, 11, 4.0, 1.0), 780);
+    assert_eq!(hit_score(0, 11, 4.0, 1.0), 0);
+}
+
+#[test]
+fn difficulty_multiplier_test() {
+    // HP6 CS4 OD8, 463 objects in a little over 4 minutes
+    assert_eq!(difficulty_multiplier_from(6.0, 4.0, 8.0, 463, 250.0), 4.0);
+    assert_eq!(difficulty_multiplier_from(0.0, 0.0, 0.0, 0, 0.0), 0.0);
+    assert_eq!(difficulty_multiplier_from(10.0, 10.0, 10.0, 10_000, 10.0), 6.0);
+}
+
+#[test]
+fn score_v2_test() {
+    let mut perfect = ScoreV2::new(10, 1.0);
+    for combo in 0..10 {
+        perfect.on_hit(300, combo);
+    }
+    assert_eq!(perfect.value(), 1_000_000);
+
+    let mut with_miss = ScoreV2::new(10, 1.0);
+    for combo in 0..9 {
+        with_miss.on_hit(300, combo);
+    }
+    with_miss.on_hit(0, 9);
+    assert!(with_miss.value() < perfect.value());
+
+    with_miss.reset();
+    assert_eq!(with_miss.value(), 0);
+}
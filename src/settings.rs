@@ -0,0 +1,106 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::str::FromStr;
+
+pub const SETTINGS_FILE: &'static str = "rusty-osu.cfg";
+
+const UNIVERSAL_OFFSET_KEY: &'static str = "UniversalOffset";
+const LOCAL_OFFSET_KEY_PREFIX: &'static str = "LocalOffset.";
+
+// user settings, persisted as `Key = Value` lines
+#[derive(Debug, Default, Clone)]
+pub struct Settings {
+    // compensates the audio output latency of the machine, in ms
+    pub universal_offset_ms: i32,
+    // per beatmap offsets in ms, keyed by the beatmap's md5 hash
+    pub local_offsets_ms: BTreeMap<String, i32>,
+}
+
+impl Settings {
+    pub fn load() -> Settings {
+        Settings::load_from(Path::new(SETTINGS_FILE))
+    }
+
+    pub fn load_from(path: &Path) -> Settings {
+        match std::fs::read_to_string(path) {
+            Ok(text) => Settings::parse(&text),
+            Err(_) => {
+                log::info!("No settings found at {:?}, using defaults", path);
+                Settings::default()
+            }
+        }
+    }
+
+    pub fn save(&self) {
+        if let Err(err) = std::fs::write(SETTINGS_FILE, self.serialize()) {
+            log::error!("Failed to save settings: {}", err);
+        }
+    }
+
+    pub fn local_offset_ms(&self, beatmap_hash: &str) -> i32 {
+        self.local_offsets_ms.get(beatmap_hash)
+            .cloned()
+            .unwrap_or(0)
+    }
+
+    pub fn set_local_offset_ms(&mut self, beatmap_hash: &str, offset: i32) {
+        if offset == 0 {
+            self.local_offsets_ms.remove(beatmap_hash);
+        } else {
+            self.local_offsets_ms.insert(beatmap_hash.to_string(), offset);
+        }
+    }
+
+    // total offset to apply to the gameplay clock, in seconds.
+    // `universal_override_ms` replaces the saved universal offset without touching it
+    pub fn audio_offset(&self, beatmap_hash: &str, universal_override_ms: Option<i32>) -> f64 {
+        let universal_offset_ms = universal_override_ms.unwrap_or(self.universal_offset_ms);
+        (universal_offset_ms + self.local_offset_ms(beatmap_hash)) as f64 / 1000.0
+    }
+
+    fn parse(text: &str) -> Settings {
+        let mut settings = Settings::default();
+        for line in text.lines() {
+            let mut key_value = line.splitn(2, "=").map(str::trim);
+            let (key, value) = match (key_value.next(), key_value.next()) {
+                (Some(key), Some(value)) => (key, value),
+                _ => continue,
+            };
+            let value = match i32::from_str(value) {
+                Ok(value) => value,
+                Err(_) => {
+                    log::warn!("Settings: ignoring invalid value for {}", key);
+                    continue;
+                }
+            };
+            if key == UNIVERSAL_OFFSET_KEY {
+                settings.universal_offset_ms = value;
+            } else if key.starts_with(LOCAL_OFFSET_KEY_PREFIX) {
+                let beatmap_hash = &key[LOCAL_OFFSET_KEY_PREFIX.len()..];
+                settings.set_local_offset_ms(beatmap_hash, value);
+            }
+        }
+        settings
+    }
+
+    fn serialize(&self) -> String {
+        let mut text = format!("{} = {}\n", UNIVERSAL_OFFSET_KEY, self.universal_offset_ms);
+        for (beatmap_hash, offset) in self.local_offsets_ms.iter() {
+            text.push_str(&format!("{}{} = {}\n", LOCAL_OFFSET_KEY_PREFIX, beatmap_hash, offset));
+        }
+        text
+    }
+}
+
+#[test]
+fn settings_roundtrip_test() {
+    let mut settings = Settings::default();
+    settings.universal_offset_ms = -12;
+    settings.set_local_offset_ms("abc", 5);
+    let parsed = Settings::parse(&settings.serialize());
+    assert_eq!(parsed.universal_offset_ms, -12);
+    assert_eq!(parsed.local_offset_ms("abc"), 5);
+    assert_eq!(parsed.local_offset_ms("other"), 0);
+    assert_eq!(parsed.audio_offset("abc", None), -0.007);
+    assert_eq!(parsed.audio_offset("abc", Some(20)), 0.025);
+}
@@ -1,38 +1,44 @@
 use crate::components::*;
-use crate::consts::{BASE_CIRCLE_RADIUS, HIT_WINDOW, LIFETIME};
-use crate::game_thread::EventLoopMsg;
+use crate::consts::{
+    EARLY_MISS_WINDOW, JUDGEMENT_POPUP_LIFETIME, MAP_END_DELAY, REPLAY_CATCH_UP_BUDGET, REPLAY_SEEK_JUMP,
+    REPLAY_SEEK_STEP, REPLAY_SPEEDS, SPINNER_CENTER, STRETCH_SAMPLE_RATE,
+};
+use crate::difficulty::PlayDifficulty;
+use crate::mods::Mods;
+use crate::autoplay::{Autoplay, BotObject, BotTarget};
+use crate::replay::{Replay, ReplayFrame, ReplayInput};
+use crate::scoring::ScoreBonus;
+use crate::time_stretch::time_stretch;
+use crate::game_thread::{EventLoopMsg, GameConfig, NextScene, SceneChange};
+use glutin::event::VirtualKeyCode;
 use crate::resources::*;
 use crate::utils::{circle_contains_point, lerp, btree_gt, btree_less};
-use kira::instance::{InstanceSettings, StopInstanceSettings};
+use kira::instance::{InstanceSettings, InstanceState, PauseInstanceSettings, ResumeInstanceSettings, StopInstanceSettings};
 use kira::manager::{AudioManager, AudioManagerSettings};
-use kira::sound::{SoundSettings};
+use kira::sound::{Sound, SoundSettings};
+use kira::Frame;
 use specs::{
-    Builder, Entities, Join, LazyUpdate, Read, ReadStorage, System, WorldExt, Write,
-    WriteStorage,
+    Builder, Dispatcher, Entities, Entity, Join, LazyUpdate, Read, ReadExpect, ReadStorage, System, SystemData, World, WorldExt, Write,
+    WriteExpect, WriteStorage,
 };
 use std::{ops::Deref};
 use std::time::{Instant};
+use std::path::{Path, PathBuf};
 use crate::osu_parser::*;
+use crate::beatmap_loader::BeatmapSamples;
 use kira::sound::handle::SoundHandle;
 use kira::instance::handle::InstanceHandle;
 use kira::parameter::tween::{Tween};
 
 pub struct ObjectSpawnerSystem {
     beatmap: OsuBeatMap,
+    song_path: PathBuf,
     current_hit_object_index: usize,
     timing_points_lookup: std::collections::BTreeMap<u64, f64>,
 }
 
-impl Default for ObjectSpawnerSystem {
-    fn default() -> Self {
-        const OSU_MAP: &'static str = include_str!("Niko - Made of Fire (lesjuh) [Oni].osu");
-        let beatmap = parse_osu_file(
-            OSU_MAP.lines(),
-            OsuBeatMapParseConfig {
-                pre_add_audio_lead_in: true
-            },
-        );
-
+impl ObjectSpawnerSystem {
+    pub fn new(beatmap: OsuBeatMap, song_path: PathBuf) -> Self {
         let mut timings_lookup = std::collections::BTreeMap::new();
 
         beatmap.timing_points.iter().for_each(|timing| {
@@ -41,6 +47,7 @@ impl Default for ObjectSpawnerSystem {
 
         return Self {
             beatmap,
+            song_path,
             current_hit_object_index: 0,
             timing_points_lookup: timings_lookup,
         };
@@ -50,17 +57,28 @@ impl Default for ObjectSpawnerSystem {
 impl<'a> System<'a> for ObjectSpawnerSystem {
     type SystemData = (
         Read<'a, Time>,
+        Read<'a, GameEvents>,
+        ReadExpect<'a, PlayDifficulty>,
+        Write<'a, MapProgress>,
         Entities<'a>,
         Read<'a, LazyUpdate>,
     );
 
     fn run(&mut self, (
         time,
+        events,
+        difficulty,
+        mut progress,
         entities,
         updater
     ): Self::SystemData) {
+        if events.contains(&GameEvent::Restart) {
+            self.current_hit_object_index = 0;
+            return;
+        }
+
         if let Some(obj) = self.beatmap.hit_objects.get(self.current_hit_object_index) {
-            if time.secs_since_start + LIFETIME >= obj.time_offset_in_secs {
+            if time.secs_since_start + difficulty.preempt >= obj.time_offset_in_secs {
                 let mut builder = updater
                     .create_entity(entities.deref())
                     .with(GamePos {
@@ -69,6 +87,7 @@ impl<'a> System<'a> for ObjectSpawnerSystem {
                     })
                     .with(Lifetime {
                         remaining: obj.time_offset_in_secs - time.secs_since_start,
+                        hit_at: obj.time_offset_in_secs,
                     })
                     .with(CircleHitRating::default())
                     .with(HitSound { value: obj.hit_sound });
@@ -120,17 +139,31 @@ impl<'a> System<'a> for ObjectSpawnerSystem {
                                 .unwrap_or_else(|| btree_less(&self.timing_points_lookup, obj.time_offset_in_millis).unwrap());
 
                             // do some osu math, https://osu.ppy.sh/wiki/fi/osu!_File_Formats/Osu_(file_format)#sliders
-                            let slider_duration = slider_data.length / (self.beatmap.slider_multiplier * 100.0) * timing_point / 1000.0;
+                            let slide_duration = slider_data.length / (self.beatmap.slider_multiplier * 100.0) * timing_point / 1000.0;
+                            let tick_interval = timing_point / self.beatmap.slider_tick_rate / 1000.0;
 
                             builder.with(Slider {
                                 curve_points: slider_data.curve_points.clone(),
-                                duration_in_secs: slider_duration,
+                                duration_in_secs: slide_duration * slider_data.slides as f64,
+                                slides: slider_data.slides,
                                 progress: 0.0,
                                 skia_path: path,
                                 curve: slider_curve,
                                 state: SliderState::UNTOUCHED,
+                                checkpoints: slider_checkpoints(slide_duration, slider_data.slides, tick_interval),
+                                next_checkpoint: 0,
+                                checkpoints_hit: 0,
+                                head_pos: (obj.x, obj.y),
+                                head: None,
                             })
                         }
+                        OsuBeatMapHitObjectParams::Spinner(spinner_data) => {
+                            builder.with(Spinner::new(
+                                obj.time_offset_in_secs,
+                                spinner_data.end_time_in_secs,
+                                self.beatmap.overall_difficulty,
+                            ))
+                        }
                     }
                 } else {
                     builder
@@ -141,10 +174,12 @@ impl<'a> System<'a> for ObjectSpawnerSystem {
                 self.current_hit_object_index += 1;
             }
         };
+
+        progress.all_objects_spawned = self.current_hit_object_index >= self.beatmap.hit_objects.len();
     }
     fn setup(&mut self, world: &mut specs::World) {
         let mut events = world.fetch_mut::<GameEvents>();
-        events.emit(GameEvent::SongLoad("./assets/Niko - Made of Fire.mp3".to_string()));
+        events.emit(GameEvent::SongLoad(self.song_path.to_string_lossy().to_string()));
     }
 }
 
@@ -154,6 +189,7 @@ impl<'a> System<'a> for CircleLifetimeSystem {
     type SystemData = (
         ReadStorage<'a, Lifetime>,
         ReadStorage<'a, Circle>,
+        ReadExpect<'a, PlayDifficulty>,
         Entities<'a>,
         Read<'a, LazyUpdate>,
     );
@@ -161,16 +197,18 @@ impl<'a> System<'a> for CircleLifetimeSystem {
     fn run(&mut self, (
         lifetimes,
         circles,
+        difficulty,
         entities,
         updater,
     ): Self::SystemData) {
         for (_, lifetime, entity) in (&circles, &lifetimes, &entities).join() {
-            if lifetime.remaining <= -HIT_WINDOW {
+            if lifetime.remaining <= -difficulty.hit_windows.meh {
                 updater.insert(
                     entity,
                     DespawnObject {
                         reason: DespawnObjectReason::CircleHit(CircleHitRating::MISS),
                         despawned_at: Instant::now(),
+                        hit_error: None,
                     },
                 );
             }
@@ -188,6 +226,7 @@ impl<'a> System<'a> for SliderLifetimeSystem {
         Entities<'a>,
         Read<'a, LazyUpdate>,
         Read<'a, Time>,
+        ReadExpect<'a, PlayDifficulty>,
         Write<'a, GameEvents>,
     );
 
@@ -198,6 +237,7 @@ impl<'a> System<'a> for SliderLifetimeSystem {
         entities,
         updater,
         time,
+        difficulty,
         mut game_events,
     ): Self::SystemData) {
         for (
@@ -206,10 +246,16 @@ impl<'a> System<'a> for SliderLifetimeSystem {
             pos,
             entity,
         ) in (&lifetimes, &mut sliders, &mut game_poses, &entities).join() {
+            if slider.head.is_none() && lifetime.remaining <= -difficulty.hit_windows.meh {
+                slider.head = Some(CircleHitRating::MISS);
+                game_events.emit(GameEvent::SliderHead(CircleHitRating::MISS, None, slider.head_pos));
+            }
+
             if lifetime.remaining <= 0.0 {
                 // start progressing the slider
-                slider.progress = lifetime.remaining.abs() / slider.duration_in_secs;
-                let t = slider.progress as f32;
+                let elapsed = lifetime.remaining.abs();
+                slider.progress = elapsed / slider.duration_in_secs;
+                let t = slider.curve_position(elapsed) as f32;
                 match &slider.curve {
                     SliderCurve::Linear(line) => {
                         pos.x = lerp(line.start.0, line.end.0, t);
@@ -233,7 +279,20 @@ impl<'a> System<'a> for SliderLifetimeSystem {
                     }
                 };
 
-                if lifetime.remaining.abs() >= slider.duration_in_secs {
+                // ticks, repeats and the end only count while the slider is held
+                while let Some(checkpoint) = slider.checkpoints.get(slider.next_checkpoint) {
+                    if elapsed < checkpoint.at {
+                        break;
+                    }
+                    let hit = matches!(slider.state, SliderState::DRAGGING(_));
+                    if hit {
+                        slider.checkpoints_hit += 1;
+                    }
+                    game_events.emit(GameEvent::SliderCheckpoint(checkpoint.kind, hit, (pos.x, pos.y)));
+                    slider.next_checkpoint += 1;
+                }
+
+                if elapsed >= slider.duration_in_secs {
                     if let SliderState::DRAGGING(v) = slider.state {
                         let change = slider.go_to_state(SliderState::FINISHED(v / slider.duration_in_secs, time.now));
                         game_events.emit_on_slider_change(change);
@@ -241,8 +300,9 @@ impl<'a> System<'a> for SliderLifetimeSystem {
                     updater.insert(
                         entity,
                         DespawnObject {
-                            reason: DespawnObjectReason::SliderEnd(slider.state),
+                            reason: DespawnObjectReason::SliderEnd(slider.rating()),
                             despawned_at: Instant::now(),
+                            hit_error: None,
                         },
                     );
                 }
@@ -251,6 +311,79 @@ impl<'a> System<'a> for SliderLifetimeSystem {
     }
 }
 
+pub struct SpinnerSystem;
+
+impl<'a> System<'a> for SpinnerSystem {
+    type SystemData = (
+        Read<'a, Time>,
+        Read<'a, GameArea>,
+        Read<'a, GameCursor>,
+        Read<'a, GameInputState>,
+        ReadStorage<'a, Lifetime>,
+        ReadStorage<'a, DespawnObject>,
+        WriteStorage<'a, Spinner>,
+        Write<'a, GameEvents>,
+        Entities<'a>,
+        Read<'a, LazyUpdate>,
+    );
+
+    fn run(&mut self, (
+        time,
+        game_area,
+        cursor,
+        input_state,
+        lifetimes,
+        despawn_objects,
+        mut spinners,
+        mut game_events,
+        entities,
+        updater,
+    ): Self::SystemData) {
+        let is_holding = input_state.is_key_hold(&GameInputKeyBinding::Hit1)
+            || input_state.is_key_hold(&GameInputKeyBinding::Hit2);
+
+        for (lifetime, spinner, entity, _) in (&lifetimes, &mut spinners, &entities, !&despawn_objects).join() {
+            if lifetime.is_alive() {
+                continue;
+            }
+
+            if time.secs_since_start >= spinner.end_at {
+                updater.insert(
+                    entity,
+                    DespawnObject {
+                        reason: DespawnObjectReason::SpinnerEnd(spinner.rating()),
+                        despawned_at: time.now,
+                        hit_error: None,
+                    },
+                );
+                continue;
+            }
+
+            if !is_holding {
+                spinner.last_angle = None;
+                continue;
+            }
+
+            let center = game_area.game_cords_to_screen(SPINNER_CENTER);
+            let angle = ((cursor.window_y - center.1) as f64).atan2((cursor.window_x - center.0) as f64);
+            if let Some(last_angle) = spinner.last_angle {
+                let mut delta = angle - last_angle;
+                if delta > std::f64::consts::PI {
+                    delta -= 2.0 * std::f64::consts::PI;
+                } else if delta < -std::f64::consts::PI {
+                    delta += 2.0 * std::f64::consts::PI;
+                }
+                spinner.rotation += delta.abs();
+                while spinner.rotation >= 2.0 * std::f64::consts::PI * (spinner.spins + 1) as f64 {
+                    spinner.spins += 1;
+                    game_events.emit(GameEvent::SpinnerSpin(spinner.spins > spinner.required_spins));
+                }
+            }
+            spinner.last_angle = Some(angle);
+        }
+    }
+}
+
 pub struct LifetimeSystem;
 
 impl<'a> System<'a> for LifetimeSystem {
@@ -272,15 +405,18 @@ impl<'a> System<'a> for LifetimeSystem {
             updater
         ): Self::SystemData,
     ) {
-        // progress lifetime
+        // progress lifetime, derived from the gameplay clock so it can't drift
         for lifetime in (&mut lifetimes).join() {
-            lifetime.remaining -= time.delta_seconds;
+            lifetime.remaining = lifetime.hit_at - time.secs_since_start;
         }
 
         // delete flagged entities
         for (_, entity) in (&mut despawn_objects, &entities).join() {
             updater.exec_mut(move |world| {
-                world.delete_entity(entity).unwrap();
+                // might be gone already if the map got restarted this frame
+                if world.is_alive(entity) {
+                    world.delete_entity(entity).unwrap();
+                }
             });
         }
     }
@@ -313,6 +449,7 @@ impl<'a> System<'a> for HitSystem {
         Read<'a, GameArea>,
         Read<'a, GameInputState>,
         Read<'a, GameCursor>,
+        ReadExpect<'a, PlayDifficulty>,
         ReadStorage<'a, Circle>,
         WriteStorage<'a, Slider>,
         ReadStorage<'a, Lifetime>,
@@ -330,6 +467,7 @@ impl<'a> System<'a> for HitSystem {
             game_area,
             input_state,
             cursor,
+            difficulty,
             circles,
             mut sliders,
             lifetimes,
@@ -339,7 +477,8 @@ impl<'a> System<'a> for HitSystem {
             entities,
         ): Self::SystemData,
     ) {
-        let scaled_circle_radius = BASE_CIRCLE_RADIUS * game_area.scale();
+        let hit_windows = &difficulty.hit_windows;
+        let scaled_circle_radius = difficulty.circle_radius * game_area.scale();
         let scaled_slider_circle_radius = scaled_circle_radius * 1.2;
         let hit_bindings: Vec<&'static GameInputKeyBinding> = vec![
             &GameInputKeyBinding::Hit1,
@@ -354,42 +493,90 @@ impl<'a> System<'a> for HitSystem {
             circle_contains_point(hit.0, hit.1, circle_cords.0, circle_cords.1, scaled_circle_radius)
         }
 
-        let mut process_circle_hit = |binding: &'static GameInputKeyBinding| {
-            if input_state.is_key_active(binding) {
-                for (
-                    _, // <- unused, but still needed so we only select circles
-                    lifetime,
-                    pos,
-                    hit_rating,
+        // judged against this frame's time rather than the last one, so a replay
+        // judges its input the same no matter how the frames fall
+        let at_frame_time = |lifetime: &Lifetime| Lifetime { remaining: lifetime.hit_at - time.secs_since_start, hit_at: lifetime.hit_at };
+
+        // circles and slider heads that can still be judged, earliest first
+        let mut pending: Vec<(Entity, Lifetime, (f32, f32))> = (&circles, &lifetimes, &game_poses, &entities).join()
+            .map(|(_, lifetime, pos, entity)| (entity, at_frame_time(lifetime), (pos.x, pos.y)))
+            .chain((&sliders, &lifetimes, &entities).join()
+                .filter(|(slider, _, _)| slider.head.is_none())
+                .map(|(slider, lifetime, entity)| (entity, at_frame_time(lifetime), slider.head_pos)))
+            .filter(|(_, lifetime, _)| lifetime.remaining > -hit_windows.meh)
+            .collect();
+        pending.sort_by(|a, b| a.1.hit_at.partial_cmp(&b.1.hit_at).unwrap());
+        // judged this frame, the entities are only gone next frame
+        let mut judged: Vec<Entity> = Vec::new();
+
+        // slider heads are judged on their own, the slider lives on
+        let mut judge = |entity: Entity, rating: CircleHitRating, hit_error: Option<f64>| {
+            if let Some(slider) = sliders.get_mut(entity) {
+                slider.head = Some(rating);
+                game_events.emit(GameEvent::SliderHead(rating, hit_error, slider.head_pos));
+            } else {
+                hit_rating.insert(entity, rating).unwrap();
+                updater.insert(
                     entity,
-                ) in (&circles, &lifetimes, &game_poses, &mut hit_rating, &entities).join() {
-                    if lifetime.is_in_hit_zone() {
-                        let circle_cords = game_area.game_cords_to_screen((pos.x, pos.y));
-                        if is_hit((cursor.window_x, cursor.window_y), circle_cords, scaled_circle_radius) {
-                            *hit_rating = if lifetime.is_in_perfect_hit_zone() {
-                                CircleHitRating::GREAT
-                            } else {
-                                CircleHitRating::GOOD
-                            };
-                            updater.insert(
-                                entity,
-                                DespawnObject {
-                                    reason: DespawnObjectReason::CircleHit(hit_rating.clone()),
-                                    despawned_at: time.now.clone(),
-                                },
-                            );
-                            break;
-                        }
-                    }
-                }
+                    DespawnObject {
+                        reason: DespawnObjectReason::CircleHit(rating),
+                        despawned_at: time.now.clone(),
+                        hit_error,
+                    },
+                );
             }
         };
 
+        let mut process_circle_hit = |binding: &'static GameInputKeyBinding| {
+            if !input_state.is_key_active(binding) {
+                return;
+            }
+
+            // a click goes to the earliest circle under the cursor
+            let clicked = pending.iter()
+                .enumerate()
+                .filter(|(_, (entity, _, _))| !judged.contains(entity))
+                .find(|(_, (_, _, pos))| {
+                    is_hit((cursor.window_x, cursor.window_y), game_area.game_cords_to_screen(*pos), scaled_circle_radius)
+                });
+            let (index, &(entity, lifetime, _)) = match clicked {
+                Some(clicked) => clicked,
+                None => return,
+            };
+            if lifetime.remaining > EARLY_MISS_WINDOW {
+                updater.insert(entity, Shake { started_at: time.secs_since_start });
+                return;
+            }
+
+            // notelock, earlier circles have to be hit first until their hit time has passed
+            let earlier: Vec<&(Entity, Lifetime, (f32, f32))> = pending[..index].iter()
+                .filter(|(entity, _, _)| !judged.contains(entity))
+                .collect();
+            if earlier.iter().any(|(_, lifetime, _)| lifetime.remaining > 0.0) {
+                updater.insert(entity, Shake { started_at: time.secs_since_start });
+                return;
+            }
+
+            // way too early, mashing has a cost
+            let rating = hit_windows.rating(-lifetime.remaining)
+                .unwrap_or(CircleHitRating::MISS);
+            judge(entity, rating, if rating == CircleHitRating::MISS { None } else { Some(-lifetime.remaining) });
+
+            // skipped objects are missed right away
+            let mut newly_judged = vec![entity];
+            for &(earlier_entity, _, _) in earlier {
+                judge(earlier_entity, CircleHitRating::MISS, None);
+                newly_judged.push(earlier_entity);
+            }
+            judged.extend(newly_judged);
+        };
+        hit_bindings.iter().for_each(|&binding| process_circle_hit(binding));
+
         let mut process_slider_hold = |bindings: Vec<&'static GameInputKeyBinding>| {
             let is_holding = bindings.into_iter()
                 .any(|b| input_state.is_key_hold(b));
             for (slider, lifetime, pos) in (&mut sliders, &lifetimes, &game_poses).join() {
-                if lifetime.is_in_hit_zone() || lifetime.remaining < 0.0 {
+                if lifetime.is_in_hit_zone(hit_windows) || lifetime.remaining < 0.0 {
                     let circle_cords = game_area.game_cords_to_screen((pos.x, pos.y));
                     let hit_check = || is_hit((cursor.window_x, cursor.window_y), circle_cords, scaled_slider_circle_radius);
                     let mut change = SliderStateChange::NoChange;
@@ -425,7 +612,6 @@ impl<'a> System<'a> for HitSystem {
         };
 
 
-        hit_bindings.iter().for_each(|&binding| process_circle_hit(binding));
         process_slider_hold(hit_bindings);
     }
 }
@@ -434,91 +620,634 @@ pub struct ScoringSystem;
 
 impl<'a> System<'a> for ScoringSystem {
     type SystemData = (
+        Read<'a, GameEvents>,
+        Entities<'a>,
+        Read<'a, LazyUpdate>,
         ReadStorage<'a, DespawnObject>,
+        ReadStorage<'a, GamePos>,
         Write<'a, Hp>,
         Write<'a, Combo>,
         Write<'a, Score>,
+        Write<'a, PlayStats>,
+        Write<'a, HitErrorMeter>,
+        Read<'a, Time>,
+        ReadExpect<'a, Mods>,
     );
 
-    fn run(&mut self, (objects, mut hp, mut combo, mut score): Self::SystemData) {
-        for object in (&objects).join() {
-            match &object.reason {
-                DespawnObjectReason::CircleHit(rating) => match rating {
-                    CircleHitRating::MISS => {
-                        hp.drain();
-                        combo.reset();
+    fn run(&mut self, (
+        events,
+        entities,
+        updater,
+        objects,
+        positions,
+        mut hp,
+        mut combo,
+        mut score,
+        mut stats,
+        mut meter,
+        time,
+        mods,
+    ): Self::SystemData) {
+        let spawn_popup = |judgement: Judgement, x: f32, y: f32| {
+            updater
+                .create_entity(entities.deref())
+                .with(GamePos { x, y })
+                .with(JudgementPopup { judgement, spawned_at: time.secs_since_start })
+                .build();
+        };
+
+        for event in events.iter() {
+            match event {
+                GameEvent::SliderHead(CircleHitRating::MISS, _, (x, y)) => {
+                    if combo.value > 0 {
+                        stats.slider_breaks += 1;
                     }
-                    CircleHitRating::GOOD => {
-                        combo.maintain();
-                        hp.fill();
-                        score.on_good(&combo);
+                    combo.reset();
+                    spawn_popup(Judgement::Rating(CircleHitRating::MISS), *x, *y);
+                    if mods.fails_on(CircleHitRating::MISS) {
+                        hp.fail();
                     }
-                    CircleHitRating::GREAT => {
-                        combo.maintain();
-                        hp.fill();
-                        hp.fill();
-                        score.on_great(&combo);
+                }
+                GameEvent::SliderHead(_, hit_error, _) => {
+                    score.on_bonus(ScoreBonus::SliderHead);
+                    combo.maintain();
+                    if let Some(hit_error) = hit_error {
+                        stats.hit_errors.push(*hit_error);
+                        meter.push(*hit_error, time.secs_since_start);
                     }
-                },
-                DespawnObjectReason::SliderEnd(slider_score) => {
-                    match &slider_score {
-                        SliderState::UNTOUCHED => {
-                            hp.drain();
-                            combo.reset();
-                        }
-                        SliderState::DRAGGING(_) => { unreachable!("Despawned a dragging slider") }
-                        SliderState::FINISHED(percent_completed, _) => {
-                            if percent_completed > &0.6 {
-                                hp.fill();
-                                combo.maintain();
-                                score.on_great(&combo);
-                            } else if percent_completed > &0.2 {
-                                hp.fill();
-                                combo.maintain();
-                                score.on_good(&combo);
-                            }
-                        }
+                }
+                GameEvent::SliderCheckpoint(kind, true, _) => {
+                    score.on_bonus(match kind {
+                        SliderCheckpointKind::Tick => ScoreBonus::SliderTick,
+                        SliderCheckpointKind::Repeat => ScoreBonus::SliderRepeat,
+                        SliderCheckpointKind::End => ScoreBonus::SliderEnd,
+                    });
+                    combo.maintain();
+                }
+                GameEvent::SliderCheckpoint(SliderCheckpointKind::End, false, _) => {
+                    // missing the end doesn't break combo
+                }
+                GameEvent::SliderCheckpoint(_, false, (x, y)) => {
+                    if combo.value > 0 {
+                        stats.slider_breaks += 1;
+                        spawn_popup(Judgement::SliderBreak, *x, *y);
                     }
+                    combo.reset();
+                    // a slider break counts as a miss
+                    if mods.fails_on(CircleHitRating::MISS) {
+                        hp.fail();
+                    }
+                }
+                GameEvent::SpinnerSpin(bonus) => {
+                    score.on_bonus(if *bonus { ScoreBonus::SpinnerBonusSpin } else { ScoreBonus::SpinnerSpin });
+                }
+                _ => {}
+            }
+        }
+        stats.max_combo = stats.max_combo.max(combo.value);
+
+        for (object, pos) in (&objects, &positions).join() {
+            let rating = match &object.reason {
+                DespawnObjectReason::CircleHit(rating) => *rating,
+                DespawnObjectReason::SpinnerEnd(rating) => *rating,
+                DespawnObjectReason::SliderEnd(rating) => *rating,
+            };
+
+            spawn_popup(Judgement::Rating(rating), pos.x, pos.y);
+
+            match rating {
+                CircleHitRating::MISS => {
+                    hp.drain();
+                    combo.reset();
+                }
+                CircleHitRating::MEH => {
+                    score.on_hit(rating.hit_value(), &combo);
+                    combo.maintain();
+                }
+                CircleHitRating::GOOD => {
+                    score.on_hit(rating.hit_value(), &combo);
+                    combo.maintain();
+                    hp.fill();
+                }
+                CircleHitRating::GREAT => {
+                    score.on_hit(rating.hit_value(), &combo);
+                    combo.maintain();
+                    hp.fill();
+                    hp.fill();
+                }
+            }
+
+            if mods.fails_on(rating) {
+                hp.fail();
+            }
+
+            stats.record(rating);
+            stats.max_combo = stats.max_combo.max(combo.value);
+            if let Some(hit_error) = object.hit_error {
+                stats.hit_errors.push(hit_error);
+                meter.push(hit_error, time.secs_since_start);
+            }
+        }
+    }
+}
+
+pub struct PauseSystem;
+
+impl<'a> System<'a> for PauseSystem {
+    type SystemData = (
+        Read<'a, Vec<EventLoopMsg>>,
+        Write<'a, PauseMenu>,
+        Write<'a, Time>,
+        Write<'a, GameEvents>,
+        Write<'a, SceneChange>,
+        Read<'a, LazyUpdate>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            event_loop_messages,
+            mut pause_menu,
+            mut time,
+            mut game_events,
+            mut scene_change,
+            updater,
+        ): Self::SystemData,
+    ) {
+        for event_loop_msg in event_loop_messages.iter() {
+            let key = match event_loop_msg {
+                EventLoopMsg::KeyPressed(key) => key,
+                _ => continue,
+            };
+
+            if !pause_menu.active {
+                if key == &VirtualKeyCode::Escape {
+                    pause_menu.open();
+                    time.paused = true;
+                    game_events.emit(GameEvent::Pause);
+                }
+                continue;
+            }
+
+            let resume = match key {
+                VirtualKeyCode::Escape => true,
+                VirtualKeyCode::Up => {
+                    pause_menu.select_previous();
+                    false
+                }
+                VirtualKeyCode::Down => {
+                    pause_menu.select_next();
+                    false
+                }
+                VirtualKeyCode::Return => match pause_menu.selected() {
+                    PauseMenuItem::Continue => true,
+                    PauseMenuItem::Retry => {
+                        game_events.emit(GameEvent::Restart);
+                        updater.exec_mut(restart_gameplay);
+                        false
+                    }
+                    PauseMenuItem::Quit => {
+                        scene_change.request(NextScene::SongSelect);
+                        false
+                    }
+                },
+                _ => false,
+            };
+
+            if resume {
+                pause_menu.close();
+                time.paused = false;
+                game_events.emit(GameEvent::Resume);
+            }
+        }
+    }
+}
+
+pub struct JudgementPopupSystem;
+
+impl<'a> System<'a> for JudgementPopupSystem {
+    type SystemData = (
+        Read<'a, Time>,
+        Entities<'a>,
+        ReadStorage<'a, JudgementPopup>,
+    );
+
+    fn run(&mut self, (time, entities, popups): Self::SystemData) {
+        for (entity, popup) in (&entities, &popups).join() {
+            if time.secs_since_start - popup.spawned_at > JUDGEMENT_POPUP_LIFETIME {
+                entities.delete(entity).unwrap();
+            }
+        }
+    }
+}
+
+// resets the play to the very beginning, systems holding their own state
+// reset themselves on `GameEvent::Restart`
+fn restart_gameplay(world: &mut World) {
+    world.delete_all();
+    let (song_start, rate) = {
+        let time = world.read_resource::<Time>();
+        (time.song_start, time.rate)
+    };
+    *world.write_resource::<Time>() = Time::starting_song_at(song_start).with_rate(rate);
+    let difficulty = *world.read_resource::<PlayDifficulty>();
+    *world.write_resource::<Hp>() = Hp::new(difficulty.miss_drain(), difficulty.lives);
+    *world.write_resource::<Combo>() = Combo::default();
+    world.write_resource::<Score>().reset();
+    *world.write_resource::<PauseMenu>() = PauseMenu::default();
+    *world.write_resource::<GameInputState>() = GameInputState::default();
+    world.write_resource::<MapProgress>().restart();
+    *world.write_resource::<PlayStats>() = PlayStats::default();
+    world.write_resource::<HitErrorMeter>().clear();
+    world.write_resource::<ReplayRecorder>().clear();
+    if let Some(mut playback) = world.try_fetch_mut::<ReplayPlayback>() {
+        playback.restart();
+    }
+}
+
+// moves on to the results once the last object is gone, or right away on a fail
+pub struct MapEndSystem;
+
+impl<'a> System<'a> for MapEndSystem {
+    type SystemData = (
+        Read<'a, Time>,
+        Read<'a, Score>,
+        Read<'a, PlayStats>,
+        Read<'a, Hp>,
+        ReadExpect<'a, GameConfig>,
+        ReadExpect<'a, PlayDifficulty>,
+        ReadStorage<'a, Lifetime>,
+        Write<'a, MapProgress>,
+        Write<'a, SceneChange>,
+        Read<'a, ReplayRecorder>,
+    );
+
+    fn run(&mut self, (
+        time,
+        score,
+        stats,
+        hp,
+        game_config,
+        difficulty,
+        lifetimes,
+        mut progress,
+        mut scene_change,
+        recorder,
+    ): Self::SystemData) {
+        let save_replay = || {
+            // watching a replay, check it played out the same instead
+            if let Some(watched) = &game_config.replay {
+                if watched.score == score.value() {
+                    log::info!("Replay score {} matches the recorded one", watched.score);
+                } else {
+                    log::warn!("Replay score {} differs from the recorded {}", score.value(), watched.score);
                 }
+                return;
             }
+            let replay = Replay {
+                beatmap_hash: game_config.beatmap.hash.clone(),
+                beatmap_label: game_config.beatmap.label(),
+                mods: game_config.mods,
+                difficulty_adjust: game_config.difficulty_adjust,
+                scoring_mode: game_config.scoring_mode,
+                score: score.value(),
+                frames: recorder.frames.clone(),
+            };
+            match replay.save() {
+                Ok(path) => log::info!("Replay saved to {}", path.display()),
+                Err(err) => log::error!("Failed to save the replay: {}", err),
+            }
+        };
+        let result = |failed: bool| PlayResult {
+            beatmap_label: game_config.beatmap.label(),
+            mods: game_config.mods,
+            score: score.value(),
+            scoring_mode: score.mode,
+            stats: stats.clone(),
+            hit_windows: difficulty.hit_windows,
+            failed,
+        };
+
+        if hp.failed() {
+            save_replay();
+            scene_change.request(NextScene::Results(result(true), game_config.clone()));
+            return;
+        }
+
+        // spawns land through LazyUpdate, so an object can show up after the timer started
+        if !progress.all_objects_spawned || (&lifetimes).join().next().is_some() {
+            progress.finished_at = None;
+            return;
+        }
+
+        let finished_at = *progress.finished_at.get_or_insert(time.secs_since_start);
+
+        if time.secs_since_start - finished_at >= MAP_END_DELAY {
+            save_replay();
+            scene_change.request(NextScene::Results(result(false), game_config.clone()));
         }
     }
 }
 
-pub struct InputSystem;
+// feeds a replay's input back at the gameplay time it was recorded at,
+// left/right seek and up/down change the playback speed
+pub struct ReplayPlaybackSystem;
+
+impl<'a> System<'a> for ReplayPlaybackSystem {
+    type SystemData = (
+        Read<'a, Vec<EventLoopMsg>>,
+        Read<'a, PauseMenu>,
+        Write<'a, Time>,
+        WriteExpect<'a, ReplayPlayback>,
+        Write<'a, GameEvents>,
+        Read<'a, LazyUpdate>,
+    );
+
+    fn run(&mut self, (
+        event_loop_messages,
+        pause_menu,
+        mut time,
+        mut playback,
+        mut game_events,
+        updater,
+    ): Self::SystemData) {
+        playback.due.clear();
+        playback.holding = false;
+        playback.catching_up = false;
+
+        let mut seek_to = None;
+        for event_loop_msg in event_loop_messages.iter() {
+            let key = match event_loop_msg {
+                // the pause menu has its own use for the arrow keys
+                EventLoopMsg::KeyPressed(key) if !pause_menu.active => key,
+                _ => continue,
+            };
+            let speed_index = REPLAY_SPEEDS.iter()
+                .position(|speed| *speed == playback.speed)
+                .unwrap_or(0);
+            match key {
+                VirtualKeyCode::Left => seek_to = Some(time.secs_since_start - REPLAY_SEEK_JUMP),
+                VirtualKeyCode::Right => seek_to = Some(time.secs_since_start + REPLAY_SEEK_JUMP),
+                VirtualKeyCode::Up => playback.speed = REPLAY_SPEEDS[(speed_index + 1).min(REPLAY_SPEEDS.len() - 1)],
+                VirtualKeyCode::Down => playback.speed = REPLAY_SPEEDS[speed_index.saturating_sub(1)],
+                _ => {}
+            }
+        }
+        time.rate = playback.base_rate * playback.speed;
+
+        // input only plays out the same from the start, so seeking replays up to the target
+        if let Some(seek_to) = seek_to {
+            playback.seeking_to = Some(seek_to);
+            game_events.emit(GameEvent::Restart);
+            updater.exec_mut(restart_gameplay);
+            return;
+        }
+
+        let frame_start = time.frame_start();
+        if time.paused {
+            // anything held back stays that way until the pause is over
+            time.set_frame_end(frame_start);
+            return;
+        }
+
+        if let Some(seeking_to) = playback.seeking_to {
+            // the recorded frames one by one, as many as fit in a frame, even ahead of the clock
+            match playback.next_frame() {
+                Some(frame) if frame.at < seeking_to && frame.at <= frame_start + REPLAY_SEEK_STEP => {
+                    time.set_frame_end(frame.at);
+                    playback.take_frame();
+                }
+                // nothing was recorded for a while, gameplay steps ahead on its own
+                _ => time.set_frame_end((frame_start + REPLAY_SEEK_STEP).min(seeking_to)),
+            }
+            if time.secs_since_start >= seeking_to {
+                playback.seeking_to = None;
+            } else {
+                playback.catching_up = true;
+            }
+            return;
+        }
+
+        match playback.next_frame() {
+            // frames without input weren't recorded, gameplay runs frames of its own
+            // up to where the last of them ended
+            Some(ReplayFrame { at, input: ReplayInput::Idle }) if at <= time.secs_since_start => {
+                time.set_frame_end(at);
+                playback.take_frame();
+            }
+            Some(ReplayFrame { input: ReplayInput::Idle, .. }) => {}
+            // a frame with input started where the recorded frame before it ended, it gets
+            // a frame of its own that ends right where it did, so the input is judged the same
+            Some(frame) if frame.at <= time.secs_since_start => {
+                time.set_frame_end(frame.at);
+                playback.take_frame();
+            }
+            // not due yet, the frame is held back until it is
+            Some(_) => {
+                time.set_frame_end(frame_start);
+                playback.holding = true;
+            }
+            None => {}
+        }
+        // playback running at fewer frames than were recorded falls behind by more than one
+        playback.catching_up = matches!(playback.next_frame(), Some(frame) if frame.at <= time.clock());
+    }
+}
+
+// runs a frame of gameplay, then the recorded frames a replay has fallen behind on,
+// as many as there's time for. the next frame carries on with the rest
+pub fn run_gameplay_frame(world: &mut World, dispatcher: &mut Dispatcher<'static, 'static>) {
+    let started_at = Instant::now();
+    // events from outside of gameplay, like a resize, stay for the whole frame
+    let outside_events = world.read_resource::<GameEvents>().emitted();
+    dispatcher.dispatch(world);
+    world.maintain();
+
+    let catching_up = |world: &World| matches!(world.try_fetch::<ReplayPlayback>(), Some(playback) if playback.catching_up);
+    while catching_up(world) && started_at.elapsed() < REPLAY_CATCH_UP_BUDGET {
+        // the frame before already handled the input and its events
+        world.write_resource::<Vec<EventLoopMsg>>().clear();
+        world.write_resource::<GameEvents>().truncate(outside_events);
+        world.write_resource::<Time>().catch_up();
+        dispatcher.dispatch(world);
+        world.maintain();
+    }
+}
+
+// runs the wrapped system only on frames a replay doesn't hold back,
+// one that has no recorded frame of its own mustn't judge anything
+pub struct ReplayGated<S>(pub S);
+
+impl<'a, S> System<'a> for ReplayGated<S> where S: System<'a>, S::SystemData: SystemData<'a> {
+    type SystemData = (Option<ReadExpect<'a, ReplayPlayback>>, S::SystemData);
+
+    fn run(&mut self, (playback, data): Self::SystemData) {
+        if matches!(playback, Some(playback) if playback.holding) {
+            return;
+        }
+        self.0.run(data);
+    }
+
+    fn setup(&mut self, world: &mut World) {
+        self.0.setup(world);
+    }
+}
+
+#[derive(Default)]
+pub struct InputSystem {
+    // drives the cursor, the hit keys or both instead of the player
+    autoplay: Option<Autoplay>,
+}
+
+impl InputSystem {
+    pub fn new(mods: Mods, difficulty: &PlayDifficulty, watching_replay: bool) -> Self {
+        Self {
+            // a replay has the bot's input recorded already
+            autoplay: if watching_replay { None } else { Autoplay::new(mods, difficulty) },
+        }
+    }
+}
 
 impl<'a> System<'a> for InputSystem {
     type SystemData = (
         Read<'a, Vec<EventLoopMsg>>,
         Read<'a, Time>,
+        Read<'a, PauseMenu>,
+        Read<'a, GameArea>,
         Write<'a, GameCursor>,
         Write<'a, GameInputState>,
+        Write<'a, ReplayRecorder>,
+        ReadStorage<'a, Circle>,
+        ReadStorage<'a, Slider>,
+        ReadStorage<'a, Spinner>,
+        ReadStorage<'a, Lifetime>,
+        ReadStorage<'a, GamePos>,
+        ReadStorage<'a, DespawnObject>,
+        Option<ReadExpect<'a, ReplayPlayback>>,
     );
 
     fn run(
         &mut self,
-        (event_loop_messages, time, mut game_cursor, mut game_input_state): Self::SystemData,
+        (
+            event_loop_messages,
+            time,
+            pause_menu,
+            game_area,
+            mut game_cursor,
+            mut game_input_state,
+            mut recorder,
+            circles,
+            sliders,
+            spinners,
+            lifetimes,
+            positions,
+            despawned,
+            playback,
+        ): Self::SystemData,
     ) {
         game_input_state.clear_frame();
 
+        let synthetic = match &mut self.autoplay {
+            _ if playback.is_some() => playback.iter()
+                .flat_map(|playback| playback.due.iter())
+                .filter_map(|input| match *input {
+                    ReplayInput::CursorMoved(x, y) if game_area.has_matrix() => {
+                        let (x, y) = game_area.game_cords_to_screen((x, y));
+                        Some(EventLoopMsg::MouseMovedBy(x as f64, y as f64))
+                    }
+                    ReplayInput::CursorMoved(_, _) => None,
+                    ReplayInput::KeyPressed(binding) => Some(EventLoopMsg::KeyPressed(key_vk_for_key_binding(binding))),
+                    ReplayInput::KeyReleased(binding) => Some(EventLoopMsg::KeyReleased(key_vk_for_key_binding(binding))),
+                    ReplayInput::Idle => None,
+                })
+                .collect(),
+            Some(autoplay) if !time.paused && !pause_menu.active => {
+                let mut targets: Vec<BotTarget> = (&circles, &lifetimes, &positions, !&despawned).join()
+                    .map(|(_, lifetime, pos, _)| BotTarget { object: BotObject::Circle, lifetime: *lifetime, pos: (pos.x, pos.y) })
+                    .chain((&sliders, &lifetimes, &positions, !&despawned).join()
+                        .map(|(slider, lifetime, pos, _)| match slider.head {
+                            None => BotTarget { object: BotObject::SliderHead, lifetime: *lifetime, pos: slider.head_pos },
+                            Some(_) => BotTarget { object: BotObject::SliderBall, lifetime: *lifetime, pos: (pos.x, pos.y) },
+                        }))
+                    .chain((&spinners, &lifetimes, !&despawned).join()
+                        .map(|(spinner, lifetime, _)| BotTarget {
+                            object: BotObject::Spinner { end_at: spinner.end_at },
+                            lifetime: *lifetime,
+                            pos: SPINNER_CENTER,
+                        }))
+                    .collect();
+                targets.sort_by(|a, b| a.lifetime.hit_at.partial_cmp(&b.lifetime.hit_at).unwrap());
 
-        for event_loop_msg in event_loop_messages.iter() {
+                // relax clicks where the player's cursor is now, not where it was last frame
+                let player_window_cursor = event_loop_messages.iter()
+                    .rev()
+                    .find_map(|event_loop_msg| match event_loop_msg {
+                        EventLoopMsg::MouseMovedBy(x, y) => Some((*x as f32, *y as f32)),
+                        _ => None,
+                    })
+                    .unwrap_or((game_cursor.window_x, game_cursor.window_y));
+                let player_cursor = if game_area.has_matrix() {
+                    Some(game_area.screen_cords_to_game(player_window_cursor))
+                } else {
+                    None
+                };
+                let mut events = autoplay.frame(time.secs_since_start, &targets, player_cursor);
+                if autoplay.moves_cursor() && game_area.has_matrix() {
+                    let (x, y) = game_area.game_cords_to_screen(autoplay.cursor());
+                    events.push(EventLoopMsg::MouseMovedBy(x as f64, y as f64));
+                }
+                events
+            }
+            _ => Vec::new(),
+        };
+
+        // synthetic input replaces whatever part of the player's input it takes over
+        let (replaces_cursor, replaces_keys) = match &self.autoplay {
+            _ if playback.is_some() => (true, true),
+            Some(autoplay) => (autoplay.moves_cursor(), autoplay.presses_keys()),
+            None => (false, false),
+        };
+        let is_bot_input = |event_loop_msg: &EventLoopMsg| match event_loop_msg {
+            EventLoopMsg::MouseMovedBy(_, _) => replaces_cursor,
+            EventLoopMsg::KeyPressed(key) | EventLoopMsg::KeyReleased(key) => {
+                replaces_keys && key_binding_for_vk(key).is_some()
+            }
+            _ => false,
+        };
+        let real = event_loop_messages.iter()
+            .filter(|event_loop_msg| !is_bot_input(event_loop_msg));
+
+        for event_loop_msg in real.chain(synthetic.iter()) {
             match event_loop_msg {
                 EventLoopMsg::Quit => {
                     game_input_state.quitting = true;
                 }
+                EventLoopMsg::KeyPressed(_) if pause_menu.active => {
+                    // the pause menu consumes key presses
+                }
                 EventLoopMsg::KeyPressed(key) => {
                     game_input_state.active_set.insert(key.clone());
                     game_input_state.last_active_keys_map.insert(key.clone(), time.now);
                     game_input_state.hold_set.insert(key.clone());
+                    if let Some(binding) = key_binding_for_vk(key) {
+                        recorder.record(&time, ReplayInput::KeyPressed(binding));
+                    }
                 }
                 EventLoopMsg::Resized(_, _) => {}
                 EventLoopMsg::MouseMovedBy(x, y) => {
                     game_cursor.window_x = *x as f32;
                     game_cursor.window_y = *y as f32;
+                    // recorded in game area coordinates, the window size doesn't matter on playback
+                    if game_area.has_matrix() {
+                        let (x, y) = game_area.screen_cords_to_game((*x as f32, *y as f32));
+                        recorder.record(&time, ReplayInput::CursorMoved(x, y));
+                    }
                 }
                 EventLoopMsg::KeyReleased(key) => {
                     game_input_state.hold_set.remove(key);
+                    if let Some(binding) = key_binding_for_vk(key) {
+                        recorder.record(&time, ReplayInput::KeyReleased(binding));
+                    }
                 }
             }
         }
@@ -527,62 +1256,241 @@ impl<'a> System<'a> for InputSystem {
 
 pub struct AudioSystem {
     pub manager: AudioManager,
+    pub song: Option<SoundHandle>,
     pub current_song: Option<InstanceHandle>,
     pub hit_sound_normal: SoundHandle,
     pub hit_sound_finish: SoundHandle,
     pub hit_sound_clap: SoundHandle,
     pub hit_sound_whistle: SoundHandle,
     pub slider: SoundHandle,
+    // the song plays back this much faster
+    pub rate: f64,
+    // how much faster the song was time stretched when it loaded, the playback rate
+    // kira changes the pitch with only makes up the rest
+    pub stretch: f64,
+    pub nightcore_beat: Option<NightcoreBeat>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum NightcoreSample {
+    Kick,
+    Clap,
+    Finish,
+}
+
+// nightcore's drum beat, played with the hit sound samples
+pub struct NightcoreBeat {
+    // uninherited timing points as start and beat length in song secs, plus the meter
+    sections: Vec<(f64, f64, u32)>,
+    last_beat: Option<(usize, i64)>,
+}
+
+impl NightcoreBeat {
+    pub fn new(timing_points: &[TimingPoint]) -> Self {
+        let sections = timing_points.iter()
+            .filter(|timing_point| !timing_point.inherited && timing_point.beat_length > 0.0)
+            .map(|timing_point| (
+                timing_point.time_offset_in_millis as f64 / 1000.0,
+                timing_point.beat_length / 1000.0,
+                timing_point.meter,
+            ))
+            .collect();
+        Self {
+            sections,
+            last_beat: None,
+        }
+    }
+
+    // the sample to play once a new beat starts at `song_position`
+    pub fn advance(&mut self, song_position: f64) -> Option<NightcoreSample> {
+        let section = self.sections.iter().rposition(|(start, _, _)| *start <= song_position)?;
+        let (start, beat_length, meter) = self.sections[section];
+        let beat = ((song_position - start) / beat_length).floor() as i64;
+        if self.last_beat == Some((section, beat)) {
+            return None;
+        }
+        self.last_beat = Some((section, beat));
+
+        let meter = meter as i64;
+        let beat_in_measure = beat % meter;
+        Some(if beat_in_measure == 0 && (beat / meter) % 4 == 0 {
+            NightcoreSample::Finish
+        } else if beat_in_measure % 2 == 0 {
+            NightcoreSample::Kick
+        } else {
+            NightcoreSample::Clap
+        })
+    }
+
+    pub fn reset(&mut self) {
+        self.last_beat = None;
+    }
 }
 
-impl Default for AudioSystem {
-    fn default() -> Self {
+impl AudioSystem {
+    pub fn new(samples: &BeatmapSamples, rate: f64, stretch: f64, nightcore_beat: Option<NightcoreBeat>) -> Self {
         let mut audio_manager = AudioManager::new(AudioManagerSettings::default())
             .unwrap();
 
-        let mut load = |url: &'static str, settings: SoundSettings| audio_manager
-            .load_sound(url, settings)
-            .expect(format!("Failed to load sound {}", url).as_str());
+        // prefer the beatmap's custom sample, fall back to the default one
+        let mut load = |custom: &Option<PathBuf>, default: &'static str, settings: SoundSettings| {
+            if let Some(path) = custom {
+                match audio_manager.load_sound(path, settings.clone()) {
+                    Ok(sound) => return sound,
+                    Err(err) => log::warn!("Failed to load custom sample {:?}: {:?}", path, err),
+                }
+            }
+            audio_manager
+                .load_sound(Path::new(default), settings)
+                .expect(format!("Failed to load sound {}", default).as_str())
+        };
 
-        let hit_normal = load("assets/soft-hitnormal.wav", SoundSettings::default());
-        let hit_whistle = load("assets/soft-hitwhistle.wav", SoundSettings::default());
-        let hit_finish = load("assets/soft-hitfinish.wav", SoundSettings::default());
-        let hit_clap = load("assets/soft-hitclap.wav", SoundSettings::default());
-        let slider = load("assets/soft-sliderslide.wav", SoundSettings {
+        let hit_normal = load(&samples.hit_normal, "assets/soft-hitnormal.wav", SoundSettings::default());
+        let hit_whistle = load(&samples.hit_whistle, "assets/soft-hitwhistle.wav", SoundSettings::default());
+        let hit_finish = load(&samples.hit_finish, "assets/soft-hitfinish.wav", SoundSettings::default());
+        let hit_clap = load(&samples.hit_clap, "assets/soft-hitclap.wav", SoundSettings::default());
+        let slider = load(&samples.slider_slide, "assets/soft-sliderslide.wav", SoundSettings {
             default_loop_start: Some(0.0),
             ..SoundSettings::default()
         });
 
         Self {
             manager: audio_manager,
+            song: None,
             current_song: None,
             hit_sound_normal: hit_normal,
             hit_sound_finish: hit_finish,
             hit_sound_clap: hit_clap,
             hit_sound_whistle: hit_whistle,
             slider,
+            rate,
+            stretch,
+            nightcore_beat,
         }
     }
 }
 
+// kira only speeds songs up along with their pitch,
+// so they get time stretched into a new sound that keeps it
+fn time_stretched_sound(sound: &Sound, rate: f64) -> Sound {
+    let sample_rate = STRETCH_SAMPLE_RATE as f64;
+    let (left, right): (Vec<f32>, Vec<f32>) = (0..(sound.duration() * sample_rate) as usize)
+        .map(|i| sound.get_frame_at_position(i as f64 / sample_rate))
+        .map(|frame| (frame.left, frame.right))
+        .unzip();
+    let (left, right) = time_stretch(&left, &right, rate);
+    let frames = left.into_iter()
+        .zip(right.into_iter())
+        .map(|(left, right)| Frame::new(left, right))
+        .collect();
+    Sound::from_frames(STRETCH_SAMPLE_RATE, frames, SoundSettings::default())
+}
+
 impl<'a> System<'a> for AudioSystem {
     type SystemData = (
         Read<'a, GameEvents>,
+        Read<'a, Time>,
+        Write<'a, SongClock>,
         ReadStorage<'a, DespawnObject>,
         ReadStorage<'a, HitSound>,
+        Option<ReadExpect<'a, ReplayPlayback>>,
     );
 
-    fn run(&mut self, (events, despawn_objects, hit_sounds): Self::SystemData) {
+    fn run(&mut self, (events, time, mut song_clock, despawn_objects, hit_sounds, playback): Self::SystemData) {
         events.on_song_load(|song| {
-            let mut song = self
-                .manager
-                .load_sound(song, SoundSettings::default())
-                .expect("Failed to load song");
-            let handle = song.play(InstanceSettings::default())
-                .unwrap();
-            self.current_song = Some(handle);
+            let song = if self.stretch == 1.0 {
+                self.manager
+                    .load_sound(song, SoundSettings::default())
+                    .expect("Failed to load song")
+            } else {
+                let sound = Sound::from_file(song, SoundSettings::default())
+                    .expect("Failed to load song");
+                self.manager
+                    .add_sound(time_stretched_sound(&sound, self.stretch))
+                    .expect("Failed to load song")
+            };
+            self.song = Some(song);
         });
 
+        if events.contains(&GameEvent::Restart) {
+            if let Some(instance) = &mut self.current_song {
+                instance.stop(StopInstanceSettings::default())
+                    .unwrap();
+            }
+            self.slider.stop(StopInstanceSettings::default()).unwrap();
+            self.current_song = None;
+            if let Some(beat) = &mut self.nightcore_beat {
+                beat.reset();
+            }
+            *song_clock = SongClock::default();
+            // the song starts over once the lead in has passed again
+            return;
+        }
+
+        if events.contains(&GameEvent::Pause) {
+            if let Some(instance) = &mut self.current_song {
+                instance.pause(PauseInstanceSettings::default())
+                    .unwrap();
+            }
+            self.slider.stop(StopInstanceSettings::default()).unwrap();
+        }
+
+        if events.contains(&GameEvent::Resume) {
+            if let Some(instance) = &mut self.current_song {
+                instance.resume(ResumeInstanceSettings::default())
+                    .unwrap();
+            }
+        }
+
+        // a replay fast forwarding to where it was seeked to stays quiet
+        if matches!(&playback, Some(playback) if playback.is_seeking()) {
+            return;
+        }
+
+        // replay playback speed changes the rate on the fly
+        if self.rate != time.rate {
+            self.rate = time.rate;
+            if let Some(instance) = &mut self.current_song {
+                instance.set_playback_rate(self.rate / self.stretch).unwrap();
+            }
+        }
+
+        // the song waits for the lead in, gameplay time follows it from then on
+        if let Some(song) = &mut self.song {
+            if song_clock.state == SongClockState::NotStarted && time.secs_since_start >= time.song_start {
+                let settings = InstanceSettings::default()
+                    .playback_rate(self.rate / self.stretch)
+                    .start_position((time.secs_since_start - time.song_start) / self.stretch);
+                let handle = song.play(settings)
+                    .unwrap();
+                self.current_song = Some(handle);
+            }
+        }
+
+        if let Some(instance) = &self.current_song {
+            let state = match instance.state() {
+                InstanceState::Playing | InstanceState::Stopping => SongClockState::Playing,
+                InstanceState::Paused(_) | InstanceState::Pausing(_) => SongClockState::Paused,
+                InstanceState::Stopped => SongClockState::Finished,
+            };
+            // a stretched song is shorter or longer than the song itself
+            song_clock.report(state, instance.position() * self.stretch);
+        }
+
+        if let Some(beat) = &mut self.nightcore_beat {
+            if song_clock.state == SongClockState::Playing {
+                let sound = match beat.advance(song_clock.position) {
+                    Some(NightcoreSample::Kick) => Some(&mut self.hit_sound_normal),
+                    Some(NightcoreSample::Clap) => Some(&mut self.hit_sound_clap),
+                    Some(NightcoreSample::Finish) => Some(&mut self.hit_sound_finish),
+                    None => None,
+                };
+                if let Some(sound) = sound {
+                    sound.play(InstanceSettings::default()).unwrap();
+                }
+            }
+        }
+
         events.on_slider_start(|| {
             self.slider.play(InstanceSettings::default()).unwrap();
         });
@@ -598,7 +1506,7 @@ impl<'a> System<'a> for AudioSystem {
                 DespawnObjectReason::CircleHit(reason) => {
                     match reason {
                         CircleHitRating::MISS => {}
-                        CircleHitRating::GOOD | CircleHitRating::GREAT => {
+                        CircleHitRating::MEH | CircleHitRating::GOOD | CircleHitRating::GREAT => {
                             let sound = match &hit_sound.value {
                                 OsuHitObjectHitSound::Normal => &mut self.hit_sound_normal,
                                 OsuHitObjectHitSound::Whistle => &mut self.hit_sound_whistle,
@@ -610,12 +1518,163 @@ impl<'a> System<'a> for AudioSystem {
                         }
                     };
                 }
+                DespawnObjectReason::SliderEnd(CircleHitRating::MISS) => {}
                 DespawnObjectReason::SliderEnd(_) => {
                     self.hit_sound_finish.play(InstanceSettings::default())
                         .unwrap();
 
                 }
+                DespawnObjectReason::SpinnerEnd(CircleHitRating::MISS) => {}
+                DespawnObjectReason::SpinnerEnd(_) => {
+                    self.hit_sound_normal.play(InstanceSettings::default())
+                        .unwrap();
+                }
             }
         }
     }
 }
+
+// the gameplay systems without audio and rendering, playing the first `object_count`
+// objects of the bundled map with the game area covering the window 1:1
+#[cfg(test)]
+fn make_test_gameplay(mods: Mods, object_count: usize, replay: Option<&Replay>) -> (World, Dispatcher<'static, 'static>) {
+    use crate::beatmap_loader::LoadedBeatmap;
+    use crate::scoring::{make_scoring_model, ScoringMode};
+    use specs::DispatcherBuilder;
+
+    let mut beatmap = LoadedBeatmap::bundled().beatmap;
+    beatmap.hit_objects.truncate(object_count);
+    let difficulty = PlayDifficulty::new(&beatmap, mods);
+
+    let mut world = World::new();
+    world.register::<GamePos>();
+    world.register::<Circle>();
+    world.register::<Slider>();
+    world.register::<Spinner>();
+    world.register::<Lifetime>();
+    world.register::<CircleHitRating>();
+    world.register::<DespawnObject>();
+    world.register::<HitSound>();
+    world.register::<JudgementPopup>();
+    world.register::<Shake>();
+
+    let mut game_area = GameArea::default();
+    game_area.set_game_area_matrix(skia_safe::Matrix::default());
+    world.insert(game_area);
+    world.insert(Time::starting_song_at(beatmap.audio_lead_in / 1000.0));
+    world.insert(Vec::<EventLoopMsg>::new());
+    world.insert(GameEvents::default());
+    world.insert(GameCursor::default());
+    world.insert(GameInputState::default());
+    world.insert(PauseMenu::default());
+    world.insert(Hp::new(difficulty.miss_drain(), difficulty.lives));
+    world.insert(Combo::default());
+    world.insert(Score::new(ScoringMode::V1, make_scoring_model(ScoringMode::V1, &beatmap, mods)));
+    world.insert(MapProgress::new(beatmap.object_time_range_in_secs()));
+    world.insert(PlayStats::default());
+    world.insert(HitErrorMeter::default());
+    world.insert(ReplayRecorder::default());
+    if let Some(replay) = replay {
+        world.insert(ReplayPlayback::new(replay, 1.0));
+    }
+    world.insert(mods);
+    world.insert(difficulty);
+
+    let mut dispatcher = DispatcherBuilder::new();
+    if replay.is_some() {
+        dispatcher.add(ReplayPlaybackSystem, "replay-playback-system", &[]);
+    }
+    let mut dispatcher = dispatcher
+        .with(ReplayGated(InputSystem::new(mods, &difficulty, replay.is_some())), "input-system", &[])
+        .with(ReplayGated(ObjectSpawnerSystem::new(beatmap, PathBuf::new())), "object-spawner-system", &[])
+        .with(ReplayGated(HitSystem), "hit-system", &["object-spawner-system", "input-system"])
+        .with(ReplayGated(CircleLifetimeSystem), "circle-lifetime-system", &["hit-system"])
+        .with(ReplayGated(SliderLifetimeSystem), "slider-lifetime-system", &["hit-system"])
+        .with(ReplayGated(SpinnerSystem), "spinner-system", &["object-spawner-system", "input-system"])
+        .with(ReplayGated(LifetimeSystem), "lifetime-system", &["circle-lifetime-system", "slider-lifetime-system", "spinner-system"])
+        .with(ReplayGated(ScoringSystem), "scoring-system", &["lifetime-system"])
+        .build();
+    dispatcher.setup(&mut world);
+    (world, dispatcher)
+}
+
+// runs frames `frame_secs` of wall time apart until the map is over, the same way the game thread does
+#[cfg(test)]
+fn run_test_gameplay(world: &mut World, dispatcher: &mut Dispatcher<'static, 'static>, frame_secs: f64) {
+    let end_at = world.read_resource::<MapProgress>().last_object_at + MAP_END_DELAY;
+    run_test_gameplay_until(world, dispatcher, frame_secs, end_at);
+}
+
+#[cfg(test)]
+fn run_test_gameplay_until(world: &mut World, dispatcher: &mut Dispatcher<'static, 'static>, frame_secs: f64, until: f64) {
+    use crate::resources::SongClock;
+    use std::time::Duration;
+
+    while world.read_resource::<Time>().secs_since_start < until {
+        world.write_resource::<Time>().advance(Duration::from_secs_f64(frame_secs), &SongClock::default());
+        run_gameplay_frame(world, dispatcher);
+        world.write_resource::<GameEvents>().clear();
+        world.write_resource::<Vec<EventLoopMsg>>().clear();
+
+        // a replay only holds frames back until the next recorded one, it keeps up with the clock
+        let time = world.read_resource::<Time>();
+        assert!(time.clock() - time.secs_since_start < 0.5, "playback fell behind at {}", time.secs_since_start);
+    }
+}
+
+#[test]
+fn autoplay_pipeline_test() {
+    use std::str::FromStr;
+
+    // circles, a slider with repeats and short sliders
+    for frame_secs in [1.0 / 60.0, 1.0 / 240.0, 1.0 / 720.0].iter() {
+        let (mut world, mut dispatcher) = make_test_gameplay(Mods::from_str("AT").unwrap(), 40, None);
+        run_test_gameplay(&mut world, &mut dispatcher, *frame_secs);
+
+        let stats = world.read_resource::<PlayStats>();
+        assert_eq!((stats.great, stats.good, stats.meh, stats.miss), (40, 0, 0, 0), "at {} fps", 1.0 / frame_secs);
+        assert_eq!(stats.slider_breaks, 0);
+    }
+}
+
+#[test]
+fn replay_pipeline_test() {
+    use crate::scoring::ScoringMode;
+    use std::str::FromStr;
+
+    // autoplay at 20 fps is late often enough to get some 100s
+    let mods = Mods::from_str("AT").unwrap();
+    let (mut world, mut dispatcher) = make_test_gameplay(mods, 40, None);
+    run_test_gameplay(&mut world, &mut dispatcher, 1.0 / 20.0);
+    let recorded = (*world.read_resource::<PlayStats>()).clone();
+    assert!(recorded.good > 0);
+    let replay = Replay {
+        beatmap_hash: String::new(),
+        beatmap_label: String::new(),
+        mods,
+        difficulty_adjust: Default::default(),
+        scoring_mode: ScoringMode::V1,
+        score: world.read_resource::<Score>().value(),
+        frames: world.read_resource::<ReplayRecorder>().frames.clone(),
+    };
+
+    // played back at more, the same and fewer frames than were recorded,
+    // then seeked back from close to the end, which fast forwards through most of the map
+    let seek_back_at = world.read_resource::<MapProgress>().last_object_at - 1.0;
+    assert!(seek_back_at - REPLAY_SEEK_JUMP > world.read_resource::<MapProgress>().first_object_at);
+    for (frame_secs, seek_back) in [(1.0 / 144.0, false), (1.0 / 60.0, false), (1.0 / 20.0, false), (1.0 / 12.0, false), (1.0 / 60.0, true), (1.0 / 12.0, true)].iter() {
+        let (mut world, mut dispatcher) = make_test_gameplay(mods, 40, Some(&replay));
+        if *seek_back {
+            run_test_gameplay_until(&mut world, &mut dispatcher, *frame_secs, seek_back_at);
+            world.write_resource::<Vec<EventLoopMsg>>().push(EventLoopMsg::KeyPressed(VirtualKeyCode::Left));
+        }
+        run_test_gameplay(&mut world, &mut dispatcher, *frame_secs);
+
+        let stats = world.read_resource::<PlayStats>();
+        let judgements = |stats: &PlayStats| (stats.great, stats.good, stats.meh, stats.miss, stats.slider_breaks);
+        let label = format!("at {} fps, seeked back: {}", 1.0 / frame_secs, seek_back);
+        assert_eq!(judgements(&stats), judgements(&recorded), "{}", label);
+        assert_eq!(stats.hit_errors, recorded.hit_errors, "{}", label);
+        assert_eq!(world.read_resource::<Score>().value(), replay.score, "{}", label);
+    }
+}